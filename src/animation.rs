@@ -0,0 +1,248 @@
+use crate::hittable::Hit;
+use crate::hittable::Hittable;
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::Point;
+use crate::vec3::Vec3;
+
+use std::ops::Add;
+use std::ops::Mul;
+use std::ops::Sub;
+use std::rc::Rc;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    // Catmull-Rom spline through the keys.
+    Spline,
+}
+
+/// A value keyed over time, in seconds. Before the first and after the last
+/// key the value holds.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Track<T> {
+    keys: Vec<(f64, T)>,
+    interpolation: Interpolation,
+}
+
+impl<T> Track<T>
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T>,
+{
+    pub fn new(interpolation: Interpolation) -> Self {
+        Self {
+            keys: Vec::new(),
+            interpolation,
+        }
+    }
+
+    pub fn key(mut self, time: f64, value: T) -> Self {
+        let index = self.keys.partition_point(|(t, _)| *t <= time);
+        self.keys.insert(index, (time, value));
+        self
+    }
+
+    /// The value at `time`, or `None` if the track has no keys.
+    pub fn at(&self, time: f64) -> Option<T> {
+        let (first, last) = (self.keys.first()?, self.keys.last()?);
+        if time <= first.0 {
+            return Some(first.1);
+        }
+        if time >= last.0 {
+            return Some(last.1);
+        }
+
+        // keys i and i + 1 bracket the time.
+        let i = self.keys.partition_point(|(t, _)| *t <= time) - 1;
+        let (t0, p0) = self.keys[i];
+        let (t1, p1) = self.keys[i + 1];
+        let s = (time - t0) / (t1 - t0);
+
+        match self.interpolation {
+            Interpolation::Linear => Some(p0 * (1.0 - s) + p1 * s),
+            Interpolation::Spline => {
+                let (t_prev, p_prev) = self.keys[i.saturating_sub(1)];
+                let (t_next, p_next) = self.keys[(i + 2).min(self.keys.len() - 1)];
+
+                // tangents scaled to the segment, so uneven key spacing doesn't
+                // overshoot.
+                let m0 = (p1 - p_prev) * ((t1 - t0) / (t1 - t_prev));
+                let m1 = (p_next - p0) * ((t1 - t0) / (t_next - t0));
+
+                let s2 = s * s;
+                let s3 = s2 * s;
+                Some(
+                    p0 * (2.0 * s3 - 3.0 * s2 + 1.0)
+                        + m0 * (s3 - 2.0 * s2 + s)
+                        + p1 * (-2.0 * s3 + 3.0 * s2)
+                        + m1 * (s3 - s2),
+                )
+            }
+        }
+    }
+}
+
+/// Keyframed camera parameters; tracks without keys leave the camera's own
+/// setting alone.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CameraAnimation {
+    pub look_from: Track<Point>,
+    pub look_at: Track<Point>,
+    pub vfov: Track<f64>,
+    pub focus_dist: Track<f64>,
+}
+
+/// Moves an object by a keyframed translation and rotation (Euler angles in
+/// degrees, applied about x, then y, then z), evaluated at each ray's time.
+/// The wrapped object itself is shared and never rebuilt between frames.
+#[derive(Debug)]
+pub struct Animated {
+    object: Rc<dyn Hittable>,
+    translation: Track<Vec3>,
+    rotation: Track<Vec3>,
+}
+
+impl Animated {
+    pub fn new(object: Rc<dyn Hittable>, translation: Track<Vec3>, rotation: Track<Vec3>) -> Self {
+        Self {
+            object,
+            translation,
+            rotation,
+        }
+    }
+}
+
+impl Hittable for Animated {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<Hit> {
+        let offset = self.translation.at(r.time).unwrap_or_default();
+        let angles = self.rotation.at(r.time).unwrap_or_default() * (std::f64::consts::PI / 180.0);
+
+        let local_ray =
+            Ray::new(unrotate(r.origin - offset, angles), unrotate(r.dir, angles)).inherit(r);
+        let mut hit = self.object.hit(&local_ray, ray_t)?;
+
+        hit.p = rotate(hit.p, angles) + offset;
+        hit.normal = rotate(hit.normal, angles);
+        hit.geometric_normal = rotate(hit.geometric_normal, angles);
+        hit.dpdu = rotate(hit.dpdu, angles);
+        hit.dpdv = rotate(hit.dpdv, angles);
+        Some(hit)
+    }
+
+    fn materials(&self) -> Vec<Rc<dyn Material>> {
+        self.object.materials()
+    }
+}
+
+fn rotate(v: Vec3, angles: Vec3) -> Vec3 {
+    let v = rotate_axis(v, 0, angles.x());
+    let v = rotate_axis(v, 1, angles.y());
+    rotate_axis(v, 2, angles.z())
+}
+
+fn unrotate(v: Vec3, angles: Vec3) -> Vec3 {
+    let v = rotate_axis(v, 2, -angles.z());
+    let v = rotate_axis(v, 1, -angles.y());
+    rotate_axis(v, 0, -angles.x())
+}
+
+// Right-handed rotation by `angle` radians about the given coordinate axis.
+fn rotate_axis(v: Vec3, axis: usize, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+    let mut out = v;
+    out[a] = cos * v[a] - sin * v[b];
+    out[b] = sin * v[a] + cos * v[b];
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable_list::HittableList;
+    use crate::material::AlphaMasked;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+
+    #[test]
+    fn test_linear_track() {
+        let track = Track::new(Interpolation::Linear)
+            .key(0.0, 1.0)
+            .key(2.0, 3.0);
+
+        assert_eq!(track.at(-1.0), Some(1.0));
+        assert_eq!(track.at(1.0), Some(2.0));
+        assert_eq!(track.at(5.0), Some(3.0));
+    }
+
+    #[test]
+    fn test_spline_track_hits_keys() {
+        let track = Track::new(Interpolation::Spline)
+            .key(2.0, 4.0)
+            .key(0.0, 0.0)
+            .key(1.0, 1.0)
+            .key(3.0, 9.0);
+
+        for (t, value) in [(0.0, 0.0), (1.0, 1.0), (2.0, 4.0), (3.0, 9.0)] {
+            assert!((track.at(t).unwrap() - value).abs() < 1e-12);
+        }
+        let mid = track.at(1.5).unwrap();
+        assert!(mid > 1.0 && mid < 4.0);
+    }
+
+    #[test]
+    fn test_rotate_round_trip() {
+        let v = Vec3::new(1.0, 2.0, 3.0);
+        let angles = Vec3::new(0.3, -1.2, 2.0);
+        let back = unrotate(rotate(v, angles), angles);
+
+        assert!((back - v).norm() < 1e-12);
+
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        let y = Vec3::new(0.0, 1.0, 0.0);
+        assert!((rotate_axis(x, 2, std::f64::consts::FRAC_PI_2) - y).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_moved_cut_out_is_decided_once() {
+        // rays leaving from the center of a half-transparent sphere hit it
+        // half the time, whether or not it has been moved.
+        let green = Rc::new(Lambertian::new(Color::new(0.0, 1.0, 0.0)));
+        let half = Rc::new(SolidColor::new(Color::default()).with_alpha(0.5));
+        let leaf: Rc<dyn Hittable> = Rc::new(Sphere::new(
+            Point::default(),
+            1.0,
+            Rc::new(AlphaMasked::new(green, half)),
+        ));
+        let offset = Vec3::new(3.0, 0.0, 0.0);
+        let moved = Animated::new(
+            leaf.clone(),
+            Track::new(Interpolation::Linear).key(0.0, offset),
+            Track::default(),
+        );
+
+        let hit_fraction = |object: Rc<dyn Hittable>, center: Point| {
+            let mut world = HittableList::new();
+            world.add(object);
+            let n = 2000;
+            let hits = (0..n)
+                .filter(|i| {
+                    let (x, y) = ((i % 40) as f64 / 40.0, (i / 40) as f64 / 50.0);
+                    let r = Ray::new(center, Vec3::new(x, y, -1.0));
+                    world.hit(&r, Interval::new(0.001, f64::INFINITY)).is_some()
+                })
+                .count();
+            hits as f64 / n as f64
+        };
+
+        let expected = 0.5;
+        let still = hit_fraction(leaf, Point::default());
+        let animated = hit_fraction(Rc::new(moved), offset);
+        assert!((still - expected).abs() < 0.05, "{}", still);
+        assert!((animated - expected).abs() < 0.05, "{}", animated);
+    }
+}
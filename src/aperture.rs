@@ -0,0 +1,114 @@
+use crate::sampler::Sampler;
+use crate::texture::ImageTexture;
+use crate::texture::Texture;
+use crate::vec3::Point;
+use crate::vec3::Vec3;
+use crate::vec3::sample_unit_disk;
+
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+const MAX_MASK_TRIES: u32 = 64;
+
+/// Shape of the lens opening, which out-of-focus highlights take on.
+#[derive(Debug, Default, Clone)]
+pub enum Aperture {
+    #[default]
+    Disk,
+    // Regular polygon inscribed in the disk, rotated by `rotation` degrees.
+    Polygon {
+        blades: u32,
+        rotation: f64,
+    },
+    // Image over the disk's bounding square; brighter texels are more open.
+    Mask(Rc<ImageTexture>),
+}
+
+impl Aperture {
+    /// Uniformly samples a point on the opening, in lens coordinates where the
+    /// full aperture is the unit disk. Returns None if no open point of a mask
+    /// was found, and the sample should be dropped.
+    pub fn sample(&self, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        match self {
+            Self::Disk => Some(sample_unit_disk(sampler.get_2d())),
+            Self::Polygon { blades, rotation } => {
+                // pick one of the equal triangles fanning out from the center.
+                let blades = (*blades).max(3);
+                let step = 2.0 * PI / blades as f64;
+                let k = ((sampler.get_1d() * blades as f64) as u32).min(blades - 1);
+                let a0 = rotation.to_radians() + k as f64 * step;
+                let v0 = Vec3::new(a0.cos(), a0.sin(), 0.0);
+                let v1 = Vec3::new((a0 + step).cos(), (a0 + step).sin(), 0.0);
+
+                let [mut s, mut t] = sampler.get_2d();
+                if s + t > 1.0 {
+                    s = 1.0 - s;
+                    t = 1.0 - t;
+                }
+                Some(s * v0 + t * v1)
+            }
+            Self::Mask(mask) => {
+                for _ in 0..MAX_MASK_TRIES {
+                    let [u, v] = sampler.get_2d();
+                    let (x, y) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+                    let open = mask.scalar(u, v, Point::default());
+                    if open > sampler.get_1d() {
+                        return Some(Vec3::new(x, y, 0.0));
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SamplerKind;
+    use crate::vec3::cross;
+
+    #[test]
+    fn test_polygon_samples_lie_inside() {
+        let (blades, rotation) = (5, 20.0);
+        let aperture = Aperture::Polygon { blades, rotation };
+        let corner = |k: u32| {
+            let a = rotation.to_radians() + k as f64 * 2.0 * PI / blades as f64;
+            Vec3::new(a.cos(), a.sin(), 0.0)
+        };
+
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        let mut used = vec![false; blades as usize];
+        for i in 0..2000 {
+            sampler.start_pixel_sample(i, 0, 0);
+            let p = aperture.sample(sampler.as_mut()).unwrap();
+            // left of every edge, going counter-clockwise.
+            for k in 0..blades {
+                let (a, b) = (corner(k), corner(k + 1));
+                assert!(cross(b - a, p - a).z() >= -1e-12, "{:?} outside", p);
+            }
+            let angle = (p.y().atan2(p.x()) - rotation.to_radians()).rem_euclid(2.0 * PI);
+            used[(angle / (2.0 * PI) * blades as f64) as usize] = true;
+        }
+        // and they cover all of it.
+        assert!(used.iter().all(|&u| u));
+    }
+
+    #[test]
+    fn test_mask_samples_open_texels_only() {
+        let half_open = ImageTexture::from_ppm(b"P2 2 1 255\n0 255\n").unwrap();
+        let aperture = Aperture::Mask(Rc::new(half_open));
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        for i in 0..500 {
+            sampler.start_pixel_sample(i, 0, 0);
+            let p = aperture.sample(sampler.as_mut()).unwrap();
+            assert!(p.x() >= 0.0, "{:?} is masked", p);
+        }
+
+        // a closed mask drops the sample instead of falling back to the center.
+        let closed = ImageTexture::from_ppm(b"P2 1 1 255\n0\n").unwrap();
+        let aperture = Aperture::Mask(Rc::new(closed));
+        sampler.start_pixel_sample(0, 0, 0);
+        assert_eq!(aperture.sample(sampler.as_mut()), None);
+    }
+}
@@ -1,23 +1,244 @@
+use crate::animation::CameraAnimation;
+use crate::aperture::Aperture;
 use crate::color::Color;
+use crate::denoise::Denoiser;
+use crate::effects::Effects;
+use crate::film::Aovs;
+use crate::film::Film;
+use crate::film::Snapshot;
+use crate::filter::Filter;
+use crate::filter::FilterSampler;
+use crate::hittable::Hit;
 use crate::hittable::Hittable;
+use crate::image::Image;
 use crate::interval::Interval;
+use crate::material::Material;
+use crate::material::Medium;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::sampler::SamplerKind;
+use crate::spectrum::D_LINE;
+use crate::spectrum::RGB_WAVELENGTHS;
+use crate::spectrum::SampledWavelengths;
+use crate::tonemap::ToneMap;
 use crate::vec3::Point;
 use crate::vec3::Vec3;
 use crate::vec3::cross;
-use crate::vec3::random_on_unit_disk;
+use crate::vec3::dot;
+use crate::vec3::sample_unit_vector;
 
+use std::fmt;
 use std::fmt::Write;
 use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+use std::time::Instant;
 
-use rand::prelude::*;
+const OUT_STEM: &str = "out";
+const MAX_WALK_STEPS: u32 = 256;
+// Pass size for checkpointed renders that aren't otherwise progressive.
+const CHECKPOINT_PASS_SAMPLES: u32 = 16;
 
-const OUT_PATH: &str = "out.ppm";
+/// How image positions map to ray directions. Depth of field only applies to
+/// the perspective projection.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    // Parallel rays covering the perspective viewport at the focus distance.
+    Orthographic,
+    // Circular fisheye whose image height spans `vfov`, which may exceed 180.
+    Fisheye(FisheyeMapping),
+    // Full 360x180 latitude/longitude panorama.
+    Equirectangular,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FisheyeMapping {
+    // Image radius proportional to the angle off-axis.
+    Equidistant,
+    // Image radius proportional to sin(angle / 2); preserves solid angle.
+    Equisolid,
+}
+
+/// Camera settings in photographic terms, overriding `vfov` and the defocus
+/// angle. Lengths on the lens and sensor are in millimeters; the scene is
+/// assumed to be in meters.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PhysicalLens {
+    pub focal_length: f64,
+    pub sensor_width: f64,
+    pub sensor_height: f64,
+    pub f_number: f64,
+    pub focus: Focus,
+    pub iso: f64,
+    // Seconds.
+    pub shutter_time: f64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Focus {
+    Distance(f64),
+    // Focus on whatever is hit through the center of the image.
+    Auto,
+}
+
+impl PhysicalLens {
+    /// A lens on a 36x24mm full-frame sensor at ISO 100, 1/100s.
+    pub fn new(focal_length: f64, f_number: f64, focus: Focus) -> Self {
+        Self {
+            focal_length,
+            sensor_width: 36.0,
+            sensor_height: 24.0,
+            f_number,
+            focus,
+            iso: 100.0,
+            shutter_time: 0.01,
+        }
+    }
+
+    /// Vertical field of view in degrees. The image is fit inside the sensor,
+    /// cropping whichever dimension doesn't match its aspect ratio.
+    pub fn vfov(&self, aspect: f64) -> f64 {
+        let height = if aspect >= self.sensor_width / self.sensor_height {
+            self.sensor_width / aspect
+        } else {
+            self.sensor_height
+        };
+        2.0 * (height / (2.0 * self.focal_length)).atan().to_degrees()
+    }
+
+    /// Aperture radius in scene units.
+    pub fn aperture_radius(&self) -> f64 {
+        self.focal_length / (2.0 * self.f_number) / 1000.0
+    }
+
+    /// Scale applied to the rendered radiance, normalized so that the sunny-16
+    /// rule (f/16 at 1/ISO seconds) leaves the image unchanged.
+    pub fn exposure(&self) -> f64 {
+        self.iso * self.shutter_time * (16.0 / self.f_number).powf(2.0)
+    }
+}
+
+/// Renders a left and right eye view in one image, for VR. With the
+/// equirectangular projection this produces an omni-directional stereo panorama.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Stereo {
+    pub interocular: f64,
+    // Distance at which the two views line up (zero parallax); positive.
+    pub convergence: f64,
+    pub layout: StereoLayout,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StereoLayout {
+    // Left eye on the left half.
+    SideBySide,
+    // Left eye on the top half.
+    OverUnder,
+}
+
+/// A rectangle of pixels to render, in full-frame pixel coordinates, for
+/// refining one area of the image quickly.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub output: RegionOutput,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RegionOutput {
+    // Only the rendered rectangle.
+    Crop,
+    // The full frame, black outside the rectangle.
+    FullFrame,
+}
+
+/// Adaptive sampling: `samples_per_pixel` becomes the average over the
+/// image. Every pixel is sampled in rounds of `min_samples`, and pixels keep
+/// getting rounds until their relative error drops below `threshold` or the
+/// budget runs out, so converged areas hand their samples to noisy ones.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Adaptive {
+    pub min_samples: u32,
+    pub threshold: f64,
+    // Also write the number of samples each pixel got, relative to the most.
+    pub sample_map: bool,
+}
+
+/// Progressive rendering: the image is built up in passes over the whole
+/// frame, written out as it improves, until `samples_per_pixel` is reached or
+/// an optional time or noise limit is hit.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Progressive {
+    pub samples_per_pass: u32,
+    // Minimum time between intermediate writes; zero writes after every pass.
+    pub write_interval: Duration,
+    pub time_limit: Option<Duration>,
+    // Mean relative error over the image to stop at.
+    pub target_error: Option<f64>,
+}
+
+/// Periodically saves the render's progress to `<output>.checkpoint`, between
+/// passes, so that a crashed render can pick up where it stopped. Renders
+/// that aren't progressive are split into passes of 16 samples per pixel.
+/// Only a render with the same settings and scene resumes a checkpoint, and
+/// the checkpoint is removed once the render finishes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub interval: Duration,
+    // Continue from an existing checkpoint, if there is one.
+    pub resume: bool,
+}
 
-#[derive(Debug, Default, Copy, Clone)]
+// What a camera path saw: its radiance, how many times it scattered before
+// reaching the sky, and the first surface it hit along with that surface's
+// albedo.
+struct PathSample {
+    radiance: Color,
+    bounces: u32,
+    first_hit: Option<(Hit, Color)>,
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct Camera {
     pub aspect: f64,
     pub img_width: u32,
+    // Trace hero wavelengths instead of RGB; needed for dispersion.
+    pub spectral: bool,
+    pub projection: Projection,
+    pub lens: Option<PhysicalLens>,
+    pub aperture: Aperture,
+    // Off-axis clipping of the aperture by the lens barrel; 0 disables it.
+    pub cats_eye: f64,
+    // Relative change in magnification per 100nm of wavelength.
+    pub chromatic_aberration: f64,
+    pub stereo: Option<Stereo>,
+    // Applies to each eye's view when rendering stereo.
+    pub region: Option<Region>,
+    pub filter: Filter,
+    pub sampler: SamplerKind,
+    // Renders with the same seed and settings are identical.
+    pub seed: u64,
+    pub adaptive: Option<Adaptive>,
+    // With adaptive sampling too, each pass spreads its samples over the
+    // pixels that are still noisy.
+    pub progressive: Option<Progressive>,
+    pub checkpoint: Option<Checkpoint>,
+    // Also write each output variable to `out.<name>.pfm`.
+    pub aovs: bool,
+    // Also write a denoised copy of the image to `out.denoised.ppm`.
+    pub denoise: Option<Denoiser>,
+    // Bloom, glare and vignetting, applied before the display transform.
+    pub effects: Effects,
+    // Display transform for the images written as PPM.
+    pub tone_map: ToneMap,
 
     img_height: u32,
     center: Point,
@@ -25,7 +246,7 @@ pub struct Camera {
     pixel_delta_u: Vec3,
     pixel_delta_v: Vec3,
     samples_per_pixel: u32,
-    pixel_sample_scale: f64,
+    filter_sampler: FilterSampler,
     max_depth: u32,
     vfov: f64,
 
@@ -41,9 +262,18 @@ pub struct Camera {
     // Lens parameters
     defocus_angle: f64,
     focus_dist: f64,
+    // Whether `focus_dist` follows `look_at` as the camera is animated.
+    focus_on_look_at: bool,
 
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+
+    exposure: f64,
+
+    // Offset of the eye being rendered along `u`, for stereo.
+    eye: f64,
+    // Time of the frame being rendered, in seconds.
+    time: f64,
 }
 
 impl Camera {
@@ -66,32 +296,483 @@ impl Camera {
             max_depth: 50,
             defocus_angle: 0.2,
             focus_dist: (look_from - look_at).norm(),
+            focus_on_look_at: true,
             ..Default::default()
         }
     }
 
-    pub fn render<T: Hittable>(&mut self, world: &T) {
+    pub fn render<T: Hittable>(&mut self, world: &T) -> io::Result<()> {
+        let views = self.render_film(world, OUT_STEM)?;
+        self.write_film(&views, OUT_STEM)
+    }
+
+    /// Renders `frames` at `fps`, animating the camera and writing each frame
+    /// to a numbered file (`out_0000.ppm`, `out_0001.ppm`, ...). Animated
+    /// objects in the world follow the same clock.
+    pub fn render_animation<T: Hittable>(
+        &mut self,
+        world: &T,
+        animation: &CameraAnimation,
+        frames: Range<u32>,
+        fps: f64,
+    ) -> io::Result<()> {
+        for frame in frames {
+            eprintln!("Frame {}", frame);
+            self.time = frame as f64 / fps;
+            self.animate(animation);
+            let stem = format!("{}_{:04}", OUT_STEM, frame);
+            let views = self.render_film(world, &stem)?;
+            self.write_film(&views, &stem)?;
+        }
+        Ok(())
+    }
+
+    // Writes the image of the views to `<stem>.ppm`, plus any extra outputs
+    // next to it.
+    fn write_film(&self, views: &[Film], stem: &str) -> io::Result<()> {
+        let images: Vec<Image> = views
+            .iter()
+            .map(|view| view.map(|p| self.exposure * p.mean()))
+            .collect();
+        self.tone_map
+            .apply_image(&self.apply_effects(&images))
+            .write_ppm(format!("{}.ppm", stem))?;
+
+        if let Some(denoiser) = self.denoise {
+            let denoised = self.denoise_views(&denoiser, views);
+            self.tone_map
+                .apply_image(&self.apply_effects(&denoised))
+                .write_ppm(format!("{}.denoised.ppm", stem))?;
+        }
+
+        let film = self.join_views(views);
+
+        if let Some(adaptive) = self.adaptive
+            && adaptive.sample_map
+        {
+            let most = film
+                .pixels
+                .iter()
+                .map(|p| p.count)
+                .max()
+                .unwrap_or(0)
+                .max(1);
+            // counts normalized to the busiest pixel, written linearly rather
+            // than through the tone map so grey levels compare directly.
+            film.map(|p| {
+                let share = p.count as f64 / most as f64;
+                Color::new(share, share, share)
+            })
+            .write_ppm(format!("{}.samples.ppm", stem))?;
+        }
+
+        if self.aovs {
+            let aovs = film.map(|p| p.aovs().unwrap_or_default());
+            let path = |name: &str| format!("{}.{}.pfm", stem, name);
+            aovs.map(|a| a.depth).write_pfm(path("depth"))?;
+            aovs.map(|a| a.normal).write_pfm(path("normal"))?;
+            aovs.map(|a| a.albedo).write_pfm(path("albedo"))?;
+            aovs.map(|a| a.position).write_pfm(path("position"))?;
+            aovs.map(|a| a.object_id as f64)
+                .write_pfm(path("object_id"))?;
+            aovs.map(|a| a.material_id as f64)
+                .write_pfm(path("material_id"))?;
+            // the light passes add up to the image, so they share its exposure.
+            aovs.map(|a| self.exposure * a.direct)
+                .write_pfm(path("direct"))?;
+            aovs.map(|a| self.exposure * a.indirect)
+                .write_pfm(path("indirect"))?;
+            aovs.map(|a| self.exposure * a.emission)
+                .write_pfm(path("emission"))?;
+        }
+        Ok(())
+    }
+
+    // Denoises each view on its own, so that no filter reaches across the
+    // seam between the eyes.
+    fn denoise_views(&self, denoiser: &Denoiser, views: &[Film]) -> Vec<Image> {
+        views
+            .iter()
+            .map(|view| {
+                denoiser.denoise(
+                    &view.map(|p| self.exposure * p.mean()),
+                    &view.map(|p| self.exposure.powi(2) * p.variance()),
+                    &view.map(|p| p.aovs().unwrap_or_default()),
+                )
+            })
+            .collect()
+    }
+
+    // Applies the effects to each view as a whole frame, wherever a crop sits
+    // in it, then joins the views.
+    fn apply_effects(&self, views: &[Image]) -> Image {
+        let frame = (self.img_width, self.img_height);
+        let views: Vec<Image> = views
+            .iter()
+            .map(|view| self.effects.apply(view, self.film_origin(), frame))
+            .collect();
+        self.join_views(&views)
+    }
+
+    // Renders the frame, one film per view; progressive renders also write
+    // their progress to `stem` as they go.
+    fn render_film<T: Hittable>(&mut self, world: &T, stem: &str) -> io::Result<Vec<Film>> {
+        if let Some(lens) = &self.lens
+            && lens.focus == Focus::Auto
+        {
+            self.autofocus(world);
+        }
         self.initialize();
 
-        let mut ppm = String::new();
+        let eyes = match self.stereo {
+            None => vec![0.0],
+            Some(stereo) => vec![-stereo.interocular / 2.0, stereo.interocular / 2.0],
+        };
+        let mut state = Snapshot {
+            seed: self.seed,
+            // describing the whole scene isn't free; only checkpoints need it.
+            fingerprint: match self.checkpoint {
+                Some(_) => self.fingerprint(world),
+                None => 0,
+            },
+            samples: 0,
+            views: eyes.iter().map(|_| self.new_film()).collect(),
+        };
+
+        let progressive = match (self.progressive, self.checkpoint) {
+            (Some(progressive), _) => progressive,
+            (None, Some(_)) => Progressive {
+                samples_per_pass: CHECKPOINT_PASS_SAMPLES,
+                write_interval: Duration::MAX,
+                time_limit: None,
+                target_error: None,
+            },
+            (None, None) => {
+                for (&eye, view) in eyes.iter().zip(state.views.iter_mut()) {
+                    self.render_view(world, eye, view, self.samples_per_pixel);
+                }
+                return Ok(state.views);
+            }
+        };
+
+        let checkpoint_path = format!("{}.checkpoint", stem);
+        if let Some(checkpoint) = self.checkpoint
+            && checkpoint.resume
+            && Path::new(&checkpoint_path).exists()
+        {
+            let saved = Snapshot::read(&checkpoint_path)?;
+            let matches = saved.seed == state.seed
+                && saved.fingerprint == state.fingerprint
+                && saved.views.len() == state.views.len()
+                && saved
+                    .views
+                    .iter()
+                    .zip(&state.views)
+                    .all(|(a, b)| a.width == b.width && a.height == b.height);
+            if !matches {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{} is from a render with different settings or scene",
+                        checkpoint_path
+                    ),
+                ));
+            }
+            eprintln!("Resuming at {} samples per pixel", saved.samples);
+            state = saved;
+        }
 
-        writeln!(ppm, "P3"); // magic number
-        writeln!(ppm, "{} {}", self.img_width, self.img_height); // width <SP> height
-        writeln!(ppm, "255"); // maxval
+        let start = Instant::now();
+        let mut last_write = start;
+        let mut last_checkpoint = start;
+        let mut pass = 0;
+        while state.samples < self.samples_per_pixel {
+            let pass_samples = progressive
+                .samples_per_pass
+                .clamp(1, self.samples_per_pixel - state.samples);
+            let mut spent = 0;
+            for (&eye, view) in eyes.iter().zip(state.views.iter_mut()) {
+                spent += self.render_view(world, eye, view, pass_samples);
+            }
+            state.samples += pass_samples;
+            pass += 1;
 
-        for j in 0..self.img_height {
-            eprintln!("Scanlines remaining: {}", (self.img_height - j));
-            for i in 0..self.img_width {
-                let mut color = Color::new(0.0, 0.0, 0.0);
-                for _ in 0..self.samples_per_pixel {
-                    let r = self.get_ray(i, j);
-                    color += self.ray_color(&r, self.max_depth, world);
+            let error = mean_relative_error(&state.views);
+            eprintln!(
+                "Pass {}: {} samples per pixel, {:.1}s, error {:.4}",
+                pass,
+                state.samples,
+                start.elapsed().as_secs_f64(),
+                error
+            );
+
+            let out_of_time = progressive
+                .time_limit
+                .is_some_and(|limit| start.elapsed() >= limit);
+            let converged = progressive
+                .target_error
+                .is_some_and(|target| error <= target);
+            let finished = spent == 0 || converged || state.samples >= self.samples_per_pixel;
+
+            // a finished render has nothing left to resume.
+            if self.checkpoint.is_some() && finished {
+                if Path::new(&checkpoint_path).exists() {
+                    fs::remove_file(&checkpoint_path)?;
+                }
+            } else if let Some(checkpoint) = self.checkpoint
+                && (out_of_time || last_checkpoint.elapsed() >= checkpoint.interval)
+            {
+                state.write(&checkpoint_path)?;
+                last_checkpoint = Instant::now();
+            }
+            if finished || out_of_time {
+                break;
+            }
+            if last_write.elapsed() >= progressive.write_interval {
+                self.write_film(&state.views, stem)?;
+                last_write = Instant::now();
+            }
+        }
+
+        Ok(state.views)
+    }
+
+    // Identifies the render a checkpoint belongs to: the settings that affect
+    // the film, and the parameters of every object and material in the scene.
+    // Call after `initialize`.
+    fn fingerprint<T: Hittable>(&self, world: &T) -> u64 {
+        let mut hasher = Fnv::default();
+        write!(
+            hasher,
+            "{:?}",
+            (
+                self.aspect,
+                self.img_width,
+                self.spectral,
+                self.projection,
+                self.lens,
+                &self.aperture,
+                self.cats_eye,
+                self.chromatic_aberration,
+                self.stereo,
+                self.region,
+                &self.filter,
+                self.sampler,
+            )
+        )
+        .unwrap();
+        write!(
+            hasher,
+            "{:?}",
+            (
+                self.seed,
+                self.adaptive,
+                self.samples_per_pixel,
+                self.max_depth,
+                self.vfov,
+                self.look_from,
+                self.look_at,
+                self.v_up,
+                self.defocus_angle,
+                self.focus_dist,
+                self.time,
+                self.keeps_aovs(),
+            )
+        )
+        .unwrap();
+        // with adaptive sampling, each pass spreads its budget differently.
+        let samples_per_pass = self.progressive.map(|p| p.samples_per_pass);
+        write!(hasher, "{:?}", samples_per_pass).unwrap();
+        write!(hasher, "{:?}", world).unwrap();
+        hasher.0
+    }
+
+    // An empty film for one view: the region when cropping, else the frame.
+    fn new_film(&self) -> Film {
+        let (columns, rows) = self.pixel_bounds();
+        match self.region {
+            Some(region) if region.output == RegionOutput::Crop => {
+                Film::new(columns.len() as u32, rows.len() as u32)
+            }
+            _ => Film::new(self.img_width, self.img_height),
+        }
+    }
+
+    // Where the top-left pixel of the frame lands on the film.
+    fn film_origin(&self) -> (u32, u32) {
+        match self.region {
+            Some(region) if region.output == RegionOutput::Crop => {
+                let (columns, rows) = self.pixel_bounds();
+                (columns.start, rows.start)
+            }
+            _ => (0, 0),
+        }
+    }
+
+    fn join_views<P: Copy + Default>(&self, views: &[Image<P>]) -> Image<P> {
+        match (self.stereo, views) {
+            (Some(stereo), [left, right]) => match stereo.layout {
+                StereoLayout::SideBySide => Image::side_by_side(left, right),
+                StereoLayout::OverUnder => Image::over_under(left, right),
+            },
+            _ => views[0].clone(),
+        }
+    }
+
+    // Poses the camera for the current time.
+    fn animate(&mut self, animation: &CameraAnimation) {
+        if let Some(look_from) = animation.look_from.at(self.time) {
+            self.look_from = look_from;
+        }
+        if let Some(look_at) = animation.look_at.at(self.time) {
+            self.look_at = look_at;
+        }
+        if let Some(vfov) = animation.vfov.at(self.time) {
+            self.vfov = vfov;
+        }
+        match animation.focus_dist.at(self.time) {
+            Some(focus_dist) => self.focus_dist = focus_dist,
+            None if self.focus_on_look_at => {
+                self.focus_dist = (self.look_from - self.look_at).norm();
+            }
+            None => {}
+        }
+    }
+
+    // Adds `samples` samples per pixel to the view seen from an eye offset by
+    // `eye` along `u` (on average, when sampling adaptively). Returns the
+    // number of samples taken.
+    fn render_view<T: Hittable>(
+        &mut self,
+        world: &T,
+        eye: f64,
+        film: &mut Film,
+        samples: u32,
+    ) -> u64 {
+        self.eye = eye;
+        let (columns, rows) = self.pixel_bounds();
+        let (x0, y0) = self.film_origin();
+
+        let mut sampler = self.sampler.build(self.samples_per_pixel, self.seed);
+        let Some(adaptive) = self.adaptive else {
+            for j in rows.clone() {
+                if self.progressive.is_none() {
+                    eprintln!("Scanlines remaining: {}", (rows.end - j));
+                }
+                for i in columns.clone() {
+                    let mut stats = film.get(i - x0, j - y0);
+                    for _ in 0..samples {
+                        sampler.start_pixel_sample(i, j, stats.count);
+                        let (color, aovs) = self.sample_pixel(i, j, world, sampler.as_mut());
+                        stats.add(color, aovs.as_ref());
+                    }
+                    film.set(i - x0, j - y0, stats);
+                }
+            }
+            return samples as u64 * columns.len() as u64 * rows.len() as u64;
+        };
+
+        let is_noisy = |film: &Film, i: u32, j: u32| {
+            film.get(i - x0, j - y0).relative_error() > adaptive.threshold
+        };
+        let mut active: Vec<(u32, u32)> = rows
+            .clone()
+            .flat_map(|j| columns.clone().map(move |i| (i, j)))
+            .filter(|&(i, j)| is_noisy(film, i, j))
+            .collect();
+        let budget = samples as u64 * columns.len() as u64 * rows.len() as u64;
+        let mut spent = 0;
+        let mut round = 0;
+        // the first round always completes, so every pixel gets an estimate.
+        while !active.is_empty() && (round == 0 || spent < budget) {
+            eprintln!("Round {}: {} pixels still noisy", round, active.len());
+            for &(i, j) in &active {
+                let mut stats = film.get(i - x0, j - y0);
+                for _ in 0..adaptive.min_samples.max(1) {
+                    if round > 0 && spent >= budget {
+                        break;
+                    }
+                    sampler.start_pixel_sample(i, j, stats.count);
+                    let (color, aovs) = self.sample_pixel(i, j, world, sampler.as_mut());
+                    stats.add(color, aovs.as_ref());
+                    spent += 1;
                 }
-                writeln!(ppm, "{}", self.pixel_sample_scale * color);
+                film.set(i - x0, j - y0, stats);
             }
+            active.retain(|&(i, j)| is_noisy(film, i, j));
+            round += 1;
         }
 
-        fs::write(OUT_PATH, ppm);
+        spent
+    }
+
+    // Traces one sample through pixel (i, j), returning its weighted RGB
+    // contribution and, if they are needed, its output variables.
+    fn sample_pixel<T: Hittable>(
+        &self,
+        i: u32,
+        j: u32,
+        world: &T,
+        sampler: &mut dyn Sampler,
+    ) -> (Color, Option<Aovs>) {
+        let u_lambda = sampler.get_1d();
+        let mut wavelengths = self
+            .spectral
+            .then(|| SampledWavelengths::sample_uniform(u_lambda));
+        let (magnification, weight) = self.chromatic_sample(&mut wavelengths, sampler);
+        let (offset, filter_weight) = self.filter_sampler.sample(sampler.get_2d());
+
+        // outside a fisheye's image circle, or vignetted by the lens.
+        let Some(mut r) = self.get_ray(i, j, offset, magnification, sampler) else {
+            let aovs = self.keeps_aovs().then(Aovs::default);
+            return (Color::new(0.0, 0.0, 0.0), aovs);
+        };
+        r.wavelengths = wavelengths;
+        r.time = self.time;
+        let path = self.ray_color(&r, self.max_depth, world, sampler);
+        let to_rgb = |c: Color| match r.wavelengths {
+            Some(lambda) => lambda.to_rgb(c),
+            None => c,
+        };
+        let color = to_rgb(filter_weight * weight * path.radiance);
+        if !self.keeps_aovs() {
+            return (color, None);
+        }
+
+        let mut aovs = Aovs::default();
+        match path.bounces {
+            0 => aovs.emission = color,
+            1 => aovs.direct = color,
+            _ => aovs.indirect = color,
+        }
+        if let Some((hit, albedo)) = path.first_hit {
+            aovs.depth = hit.t * r.dir.norm();
+            aovs.normal = hit.outward_normal();
+            aovs.albedo = to_rgb(albedo);
+            aovs.position = hit.p;
+            aovs.object_id = hit.object_id;
+            aovs.material_id = hit.material_id;
+        }
+        (color, Some(aovs))
+    }
+
+    // Output variables are accumulated only for the outputs that use them.
+    fn keeps_aovs(&self) -> bool {
+        self.aovs || self.denoise.is_some()
+    }
+
+    // Columns and rows of the frame to render, clipped to the image.
+    fn pixel_bounds(&self) -> (Range<u32>, Range<u32>) {
+        match self.region {
+            None => (0..self.img_width, 0..self.img_height),
+            Some(region) => {
+                let x0 = region.x.min(self.img_width);
+                let y0 = region.y.min(self.img_height);
+                let x1 = region.x.saturating_add(region.width).min(self.img_width);
+                let y1 = region.y.saturating_add(region.height).min(self.img_height);
+                (x0..x1, y0..y1)
+            }
+        }
     }
 
     fn initialize(&mut self) {
@@ -105,6 +786,27 @@ impl Camera {
 
         self.center = self.look_from;
 
+        if let Some(stereo) = self.stereo {
+            assert!(
+                stereo.convergence > 0.0,
+                "stereo convergence must be positive, got {}",
+                stereo.convergence
+            );
+        }
+
+        self.exposure = 1.0;
+        if let Some(lens) = self.lens {
+            self.vfov = lens.vfov(self.aspect);
+            if let Focus::Distance(focus_dist) = lens.focus {
+                self.focus_dist = focus_dist;
+            }
+            self.defocus_angle = 2.0
+                * (lens.aperture_radius() / self.focus_dist)
+                    .atan()
+                    .to_degrees();
+            self.exposure = lens.exposure();
+        }
+
         // other camera parameters.
         let theta = deg_to_rad(self.vfov);
         let h = (theta / 2.0).tan();
@@ -136,56 +838,823 @@ impl Camera {
         self.defocus_disk_u = defocus_radius * self.u;
         self.defocus_disk_v = defocus_radius * self.v;
 
-        self.pixel_sample_scale = 1.0 / self.samples_per_pixel as f64;
+        self.filter_sampler = FilterSampler::new(&self.filter);
+    }
+
+    // Focuses on the first surface along the line of sight.
+    fn autofocus<T: Hittable>(&mut self, world: &T) {
+        let mut r = Ray::new(self.look_from, self.look_at - self.look_from);
+        // moving objects are where they are at the shutter time.
+        r.time = self.time;
+        if let Some(hit) = world.hit(&r, Interval::new(0.001, f64::INFINITY)) {
+            self.focus_dist = hit.t * r.dir.norm();
+        }
     }
 
-    fn ray_color<T: Hittable>(&self, r: &Ray, depth: u32, world: &T) -> Color {
+    fn ray_color<T: Hittable>(
+        &self,
+        r: &Ray,
+        depth: u32,
+        world: &T,
+        sampler: &mut dyn Sampler,
+    ) -> PathSample {
+        let black = Color::new(0.0, 0.0, 0.0);
         if depth == 0 {
-            return Color::new(0.0, 0.0, 0.0);
+            return PathSample {
+                radiance: black,
+                bounces: 0,
+                first_hit: None,
+            };
         }
 
         if let Some(hit) = world.hit(r, Interval::new(0.001, f64::INFINITY)) {
-            if let Some((attenuation, scattered_ray)) = hit.mat.scatter(r, &hit) {
-                return attenuation * self.ray_color(&scattered_ray, depth - 1, world);
+            let absorbed = |hit: Hit| PathSample {
+                radiance: black,
+                bounces: 1,
+                first_hit: Some((hit, black)),
+            };
+            if let Some((mut attenuation, scattered_ray)) = hit.mat.scatter(r, &hit, sampler) {
+                let albedo = attenuation;
+                attenuation *= r.transmittance(hit.t);
+                let mut scattered_ray = scattered_ray.inherit(r);
+                // refracting through the surface enters or leaves its medium.
+                if let Some(absorption) = hit.mat.absorption()
+                    && dot(scattered_ray.dir, hit.normal) < 0.0
+                {
+                    scattered_ray.absorption = if hit.front_face {
+                        absorption
+                    } else {
+                        Color::new(0.0, 0.0, 0.0)
+                    };
+                }
+                if hit.mat.is_dispersive()
+                    && let Some(lambda) = &mut scattered_ray.wavelengths
+                {
+                    attenuation *= lambda.terminate_secondary();
+                }
+                if let Some(medium) = hit.mat.medium()
+                    && hit.front_face
+                    && dot(scattered_ray.dir, hit.normal) < 0.0
+                {
+                    match self.random_walk(&hit.mat, &medium, scattered_ray, world, sampler) {
+                        Some((weight, exit_ray)) => {
+                            attenuation *= weight;
+                            scattered_ray = exit_ray;
+                        }
+                        None => return absorbed(hit),
+                    }
+                }
+                let path = self.ray_color(&scattered_ray, depth - 1, world, sampler);
+                return PathSample {
+                    radiance: attenuation * path.radiance,
+                    bounces: path.bounces + 1,
+                    first_hit: Some((hit, albedo)),
+                };
             }
-            return Color::new(0.0, 0.0, 0.0);
+            return absorbed(hit);
         }
 
         let unit_dir = r.dir.unit();
         let a = 0.5 * (unit_dir.y() + 1.0);
-        Color::new(1.0, 1.0, 1.0) * (1.0 - a) + Color::new(0.5, 0.7, 1.0) * a
+        PathSample {
+            radiance: r
+                .spectral(Color::new(1.0, 1.0, 1.0) * (1.0 - a) + Color::new(0.5, 0.7, 1.0) * a),
+            bounces: 0,
+            first_hit: None,
+        }
     }
 
-    fn get_ray(&self, i: u32, j: u32) -> Ray {
-        let offset = sample_square();
-        let pixel_sample = self.anchor
-            + (i as f64 + offset.x()) * self.pixel_delta_u
-            + (j as f64 + offset.y()) * self.pixel_delta_v;
+    // Follows a ray that has just refracted into the medium of a subsurface
+    // `material` until it leaves through its boundary again. Returns the
+    // throughput of the walk and the outgoing ray, or None if it was absorbed
+    // or lost.
+    fn random_walk<T: Hittable>(
+        &self,
+        material: &Rc<dyn Material>,
+        medium: &Medium,
+        entering: Ray,
+        world: &T,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        let sigma_t = entering.spectral(medium.sigma_t);
+        let sigma_s = entering.spectral(medium.albedo) * sigma_t;
+        let transmittance = |d: f64| {
+            Color::new(
+                (-sigma_t.x() * d).exp(),
+                (-sigma_t.y() * d).exp(),
+                (-sigma_t.z() * d).exp(),
+            )
+        };
+        let mean = |c: Color| (c.x() + c.y() + c.z()) / 3.0;
 
-        let ray_origin = if self.defocus_angle <= 0.0 {
-            self.center
-        } else {
-            self.defocus_disk_sample()
+        let mut ray = entering;
+        let mut beta = Color::new(1.0, 1.0, 1.0);
+        for _ in 0..MAX_WALK_STEPS {
+            let hit = world.hit(&ray, Interval::new(0.001, f64::INFINITY))?;
+            let boundary_dist = hit.t * ray.dir.norm();
+
+            // sample a distance from one channel, weighted over all three.
+            let [u_channel, u] = sampler.get_2d();
+            let channel = ((u_channel * 3.0) as usize).min(2);
+            let dist = -(1.0 - u).ln() / sigma_t[channel];
+
+            if dist < boundary_dist {
+                let tr = transmittance(dist);
+                beta *= sigma_s * tr / mean(sigma_t * tr);
+
+                ray = Ray::new(
+                    ray.at(dist / ray.dir.norm()),
+                    sample_unit_vector(sampler.get_2d()),
+                )
+                .inherit(&ray);
+            } else {
+                let tr = transmittance(boundary_dist);
+                beta *= tr / mean(tr);
+
+                // other surfaces inside the medium, and other pieces of it,
+                // are passed through as if made of the same medium; only the
+                // far side of our own boundary lets the walk out.
+                if hit.front_face || !Rc::ptr_eq(&hit.mat, material) {
+                    ray = Ray::new(hit.p, ray.dir).inherit(&ray);
+                    continue;
+                }
+                let (attenuation, next) = hit.mat.scatter(&ray, &hit, sampler)?;
+                let next = next.inherit(&ray);
+                beta *= attenuation;
+                if dot(next.dir, hit.normal) < 0.0 {
+                    return Some((beta, next));
+                }
+                ray = next;
+            }
+        }
+
+        None
+    }
+
+    // Lateral chromatic aberration: each wavelength is imaged at a slightly
+    // different magnification, so a sample can only carry one of them. Returns
+    // the magnification and the weight for the chosen channel.
+    fn chromatic_sample(
+        &self,
+        wavelengths: &mut Option<SampledWavelengths>,
+        sampler: &mut dyn Sampler,
+    ) -> (f64, Color) {
+        if self.chromatic_aberration == 0.0 {
+            return (1.0, Color::new(1.0, 1.0, 1.0));
+        }
+
+        let (wavelength, weight) = match wavelengths {
+            Some(lambda) => (lambda.hero(), lambda.terminate_secondary()),
+            None => {
+                let channel = ((sampler.get_1d() * 3.0) as usize).min(2);
+                let mut weight = Color::new(0.0, 0.0, 0.0);
+                weight[channel] = 3.0;
+                (RGB_WAVELENGTHS[channel], weight)
+            }
         };
-        let ray_direction = pixel_sample - ray_origin;
+        let magnification = 1.0 + self.chromatic_aberration * (wavelength - D_LINE) / 100.0;
+        (magnification, weight)
+    }
+
+    // `offset` is the sample position relative to the center of pixel (i, j).
+    fn get_ray(
+        &self,
+        i: u32,
+        j: u32,
+        offset: Vec3,
+        magnification: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<Ray> {
+        let half_width = self.img_width as f64 / 2.0;
+        let half_height = self.img_height as f64 / 2.0;
+
+        // continuous pixel coordinates, scaled about the image center.
+        let px = half_width + (i as f64 + 0.5 + offset.x() - half_width) * magnification;
+        let py = half_height + (j as f64 + 0.5 + offset.y() - half_height) * magnification;
+        let pixel_sample =
+            self.anchor + (px - 0.5) * self.pixel_delta_u + (py - 0.5) * self.pixel_delta_v;
 
-        Ray::new(ray_origin, ray_direction)
+        // image position relative to the center, with y up, in units of half
+        // the image height.
+        let x = (px - half_width) / half_height;
+        let y = (half_height - py) / half_height;
+
+        let center = self.center + self.eye * self.u;
+        match self.projection {
+            Projection::Perspective => {
+                // shift the viewport so both eyes' frusta meet at the
+                // convergence distance.
+                let pixel_sample = match self.stereo {
+                    Some(stereo) => {
+                        let shift = 1.0 - self.focus_dist / stereo.convergence;
+                        pixel_sample + self.eye * shift * self.u
+                    }
+                    None => pixel_sample,
+                };
+                let ray_origin = if self.defocus_angle <= 0.0 {
+                    center
+                } else {
+                    self.defocus_disk_sample(center, x, y, sampler)?
+                };
+                let ray_direction = pixel_sample - ray_origin;
+
+                Some(Ray::new(ray_origin, ray_direction))
+            }
+            Projection::Orthographic => {
+                let ray_origin = pixel_sample + self.focus_dist * self.w + self.eye * self.u;
+                Some(Ray::new(ray_origin, -self.w))
+            }
+            Projection::Fisheye(mapping) => {
+                let radius = (x.powf(2.0) + y.powf(2.0)).sqrt();
+                // outside the image circle.
+                if radius > 1.0 {
+                    return None;
+                }
+                let half_fov = deg_to_rad(self.vfov) / 2.0;
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => radius * half_fov,
+                    FisheyeMapping::Equisolid => 2.0 * (radius * (half_fov / 2.0).sin()).asin(),
+                };
+                if theta > std::f64::consts::PI {
+                    return None;
+                }
+
+                let phi = y.atan2(x);
+                let ray_direction =
+                    theta.sin() * (phi.cos() * self.u + phi.sin() * self.v) - theta.cos() * self.w;
+                Some(Ray::new(center, ray_direction))
+            }
+            Projection::Equirectangular => {
+                let lon = px / self.img_width as f64 - 0.5;
+                let lon = lon * 2.0 * std::f64::consts::PI;
+                let lat = y * std::f64::consts::FRAC_PI_2;
+
+                let ray_direction =
+                    lat.cos() * (lon.sin() * self.u - lon.cos() * self.w) + lat.sin() * self.v;
+                let Some(stereo) = self.stereo else {
+                    return Some(Ray::new(self.center, ray_direction));
+                };
+
+                // omni-directional stereo: the eyes circle the center, each ray
+                // leaving from the eye that would be looking along it. The
+                // baseline fades out towards the poles.
+                let tangent = lon.cos() * self.u + lon.sin() * self.w;
+                let eye = self.eye * lat.cos();
+                let ray_origin = self.center + eye * tangent;
+                let ray_direction = stereo.convergence * ray_direction - eye * tangent;
+                Some(Ray::new(ray_origin, ray_direction))
+            }
+        }
     }
 
-    fn defocus_disk_sample(&self) -> Vec3 {
-        let p = random_on_unit_disk();
-        self.center + (p[0] * self.defocus_disk_u) + (p[1] * self.defocus_disk_v)
+    // Samples the lens opening for a ray through image position (x, y). Off
+    // axis the barrel clips the aperture to its overlap with a shifted disk,
+    // giving cat's-eye highlights and darkening the corners.
+    fn defocus_disk_sample(
+        &self,
+        center: Point,
+        x: f64,
+        y: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<Vec3> {
+        let p = self.aperture.sample(sampler)?;
+        if self.cats_eye > 0.0 && (p - self.cats_eye * Vec3::new(x, y, 0.0)).norm_sq() > 1.0 {
+            return None;
+        }
+        Some(center + (p[0] * self.defocus_disk_u) + (p[1] * self.defocus_disk_v))
+    }
+}
+
+// Average relative error over the sampled pixels of all views.
+fn mean_relative_error(views: &[Film]) -> f64 {
+    let errors: Vec<f64> = views
+        .iter()
+        .flat_map(|view| view.pixels.iter())
+        .filter(|p| p.count > 0)
+        .map(|p| p.relative_error())
+        .collect();
+    errors.iter().sum::<f64>() / errors.len().max(1) as f64
+}
+
+// 64-bit FNV-1a, fed through `fmt::Write` so that settings can be hashed by
+// their debug representation.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
     }
 }
 
-fn sample_square() -> Vec3 {
-    Vec3::new(
-        rand::rng().random::<f64>() - 0.5f64,
-        rand::rng().random::<f64>() - 0.5f64,
-        0.0,
-    )
+impl Write for Fnv {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+        Ok(())
+    }
 }
 
 fn deg_to_rad(deg: f64) -> f64 {
     deg * std::f64::consts::PI / 180.0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::Interpolation;
+    use crate::animation::Track;
+    use crate::effects::Vignette;
+    use crate::hittable_list::HittableList;
+    use crate::material::Dielectric;
+    use crate::material::Lambertian;
+    use crate::material::Metal;
+    use crate::material::Subsurface;
+    use crate::sphere::Sphere;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_random_walk_passes_nested_objects() {
+        // a lossless medium with an unrelated object inside: every walk must
+        // come out again, carrying all of its light.
+        let skin: Rc<dyn Material> = Rc::new(Subsurface::new(
+            Color::new(1.0, 1.0, 1.0),
+            Color::new(0.3, 0.3, 0.3),
+            1.0,
+        ));
+        let pebble = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut world = HittableList::new();
+        world.add(Rc::new(Sphere::new(Point::default(), 1.0, skin.clone())));
+        world.add(Rc::new(Sphere::new(Point::default(), 0.4, pebble)));
+
+        let camera = Camera::default();
+        let medium = skin.medium().unwrap();
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        for i in 0..200 {
+            sampler.start_pixel_sample(i, 0, 0);
+            let entering = Ray::new(Point::new(0.0, 0.0, 0.99), Vec3::new(0.0, 0.0, -1.0));
+            let (weight, exit) = camera
+                .random_walk(&skin, &medium, entering, &world, sampler.as_mut())
+                .unwrap();
+            assert!((weight - Color::new(1.0, 1.0, 1.0)).norm() < 1e-9);
+            assert!(exit.origin.norm() > 0.999);
+        }
+    }
+
+    #[test]
+    fn test_dispersion_terminates_secondary_wavelengths() {
+        // a path through dispersive glass carries its hero wavelength alone,
+        // weighted up to stand in for all three.
+        let mut world = HittableList::new();
+        let glass = Rc::new(Dielectric::cauchy(1.5, 0.02));
+        world.add(Rc::new(Sphere::new(Point::default(), 0.5, glass)));
+
+        let camera = Camera::default();
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        for i in 0..20 {
+            sampler.start_pixel_sample(i, 0, 0);
+            let mut r = Ray::new(Point::new(0.1, 0.0, 2.0), Vec3::new(0.0, 0.0, -1.0));
+            r.wavelengths = Some(SampledWavelengths::sample_uniform(0.05 * i as f64));
+            let path = camera.ray_color(&r, 10, &world, sampler.as_mut());
+            assert!(path.bounces > 0);
+            assert!(path.radiance.x() > 1.0);
+            assert_eq!(path.radiance.y(), 0.0);
+            assert_eq!(path.radiance.z(), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_absorption_along_nested_paths() {
+        // clear glass of index 1 never reflects; the ray enters it, bounces
+        // off a mirror inside and leaves again after 3 units in the glass.
+        let absorption = Color::new(0.0, 0.5, 1.0);
+        let glass = Rc::new(Dielectric::new(1.0).with_absorption(absorption));
+        let mirror = Rc::new(Metal::new(Color::new(1.0, 1.0, 1.0), 0.0));
+        let mut world = HittableList::new();
+        world.add(Rc::new(Sphere::new(Point::default(), 2.0, glass)));
+        world.add(Rc::new(Sphere::new(Point::default(), 0.5, mirror)));
+
+        let camera = Camera::default();
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        sampler.start_pixel_sample(0, 0, 0);
+        let r = Ray::new(Point::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        let path = camera.ray_color(&r, 10, &world, sampler.as_mut());
+        assert_eq!(path.bounces, 3);
+        let sky = Color::new(0.75, 0.85, 1.0);
+        for i in 0..3 {
+            let expected = sky[i] * (-3.0 * absorption[i]).exp();
+            assert!((path.radiance[i] - expected).abs() < 1e-9);
+        }
+    }
+
+    // The direction through continuous image position (px, py) of a 16x8
+    // frame looking down -z with a 90 degree field of view.
+    fn projected(projection: Projection, px: f64, py: f64) -> Option<Vec3> {
+        let mut camera = test_camera(0);
+        camera.projection = projection;
+        camera.initialize();
+        let (i, j) = (px.floor() as u32, py.floor() as u32);
+        let offset = Vec3::new(px - i as f64 - 0.5, py - j as f64 - 0.5, 0.0);
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        sampler.start_pixel_sample(i, j, 0);
+        camera
+            .get_ray(i, j, offset, 1.0, sampler.as_mut())
+            .map(|r| r.dir.unit())
+    }
+
+    #[test]
+    fn test_fisheye_projections() {
+        let forward = Vec3::new(0.0, 0.0, -1.0);
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let fisheye = Projection::Fisheye(mapping);
+            let center = projected(fisheye, 8.0, 4.0).unwrap();
+            assert!((center - forward).norm() < 1e-9);
+
+            // the top of the image circle is half the field of view up.
+            let top = projected(fisheye, 8.0, 0.0).unwrap();
+            let expected = Vec3::new(0.0, 1.0, -1.0).unit();
+            assert!((top - expected).norm() < 1e-9, "{:?}", mapping);
+
+            // nothing outside the circle, however wide the field of view.
+            assert_eq!(projected(fisheye, 8.0, -0.1), None);
+            assert_eq!(projected(fisheye, 0.5, 0.5), None);
+        }
+    }
+
+    #[test]
+    fn test_orthographic_rays_are_parallel() {
+        let mut camera = test_camera(0);
+        camera.projection = Projection::Orthographic;
+        camera.initialize();
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        let rays: Vec<Ray> = [(0, 0), (15, 7)]
+            .iter()
+            .map(|&(i, j)| {
+                sampler.start_pixel_sample(i, j, 0);
+                camera
+                    .get_ray(i, j, Vec3::default(), 1.0, sampler.as_mut())
+                    .unwrap()
+            })
+            .collect();
+        for r in &rays {
+            assert!((r.dir.unit() - Vec3::new(0.0, 0.0, -1.0)).norm() < 1e-9);
+        }
+        let spread = rays[1].origin - rays[0].origin;
+        assert!((spread - Vec3::new(3.75, -1.75, 0.0)).norm() < 1e-9);
+    }
+
+    #[test]
+    fn test_equirectangular_longitude_and_latitude() {
+        let panorama = Projection::Equirectangular;
+        let cases = [
+            ((8.0, 4.0), Vec3::new(0.0, 0.0, -1.0)),
+            ((12.0, 4.0), Vec3::new(1.0, 0.0, 0.0)),
+            ((4.0, 4.0), Vec3::new(-1.0, 0.0, 0.0)),
+            ((0.0, 4.0), Vec3::new(0.0, 0.0, 1.0)),
+            ((8.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+            ((8.0, 2.0), Vec3::new(0.0, 1.0, -1.0).unit()),
+        ];
+        for ((px, py), expected) in cases {
+            let dir = projected(panorama, px, py).unwrap();
+            assert!(
+                (dir - expected).norm() < 1e-9,
+                "({}, {}): {:?}",
+                px,
+                py,
+                dir
+            );
+        }
+    }
+
+    #[test]
+    fn test_animated_focus_follows_look_at() {
+        let animation = CameraAnimation {
+            look_at: Track::new(Interpolation::Linear)
+                .key(0.0, Point::new(0.0, 0.0, -1.0))
+                .key(1.0, Point::new(0.0, 0.0, -3.0)),
+            ..Default::default()
+        };
+        let mut camera = test_camera(0);
+        camera.time = 1.0;
+        camera.animate(&animation);
+        assert_eq!(camera.focus_dist, 3.0);
+
+        // a focus distance that only happens to match look_at stays put.
+        camera.focus_on_look_at = false;
+        camera.time = 0.5;
+        camera.animate(&animation);
+        assert_eq!(camera.focus_dist, 3.0);
+
+        let animation = CameraAnimation {
+            focus_dist: Track::new(Interpolation::Linear).key(0.0, 5.0),
+            ..animation
+        };
+        camera.focus_on_look_at = true;
+        camera.animate(&animation);
+        assert_eq!(camera.focus_dist, 5.0);
+    }
+
+    #[test]
+    fn test_exposure_from_f_stop_shutter_and_iso() {
+        // sunny 16: f/16 at 1/100s and ISO 100.
+        let sunny = PhysicalLens::new(50.0, 16.0, Focus::Distance(1.0));
+        assert!((sunny.exposure() - 1.0).abs() < 1e-12);
+
+        // each change is one stop.
+        let wider = PhysicalLens {
+            f_number: 16.0 / 2f64.sqrt(),
+            ..sunny
+        };
+        assert!((wider.exposure() - 2.0).abs() < 1e-12);
+        let faster = PhysicalLens {
+            shutter_time: 0.005,
+            ..sunny
+        };
+        assert!((faster.exposure() - 0.5).abs() < 1e-12);
+        let sensitive = PhysicalLens {
+            iso: 200.0,
+            ..sunny
+        };
+        assert!((sensitive.exposure() - 2.0).abs() < 1e-12);
+
+        // the camera scales its radiance by the lens' exposure.
+        let mut camera = test_camera(0);
+        camera.lens = Some(PhysicalLens {
+            f_number: 8.0,
+            ..sunny
+        });
+        camera.initialize();
+        assert!((camera.exposure - 4.0).abs() < 1e-12);
+    }
+
+    #[test]
+    #[should_panic(expected = "convergence must be positive")]
+    fn test_stereo_rejects_zero_convergence() {
+        let mut camera = test_camera(0);
+        camera.stereo = Some(Stereo {
+            interocular: 0.064,
+            convergence: 0.0,
+            layout: StereoLayout::SideBySide,
+        });
+        camera.initialize();
+    }
+
+    #[test]
+    fn test_region_bounds_and_crop_size() {
+        let mut camera = test_camera(0);
+        camera.initialize();
+        let region = |x, y, width, height, output| Region {
+            x,
+            y,
+            width,
+            height,
+            output,
+        };
+
+        camera.region = Some(region(5, 2, 4, 3, RegionOutput::Crop));
+        assert_eq!(camera.pixel_bounds(), (5..9, 2..5));
+        let film = camera.new_film();
+        assert_eq!((film.width, film.height), (4, 3));
+        assert_eq!(camera.film_origin(), (5, 2));
+
+        // clipped to the 16x8 frame.
+        camera.region = Some(region(12, 6, 10, 10, RegionOutput::Crop));
+        assert_eq!(camera.pixel_bounds(), (12..16, 6..8));
+        let film = camera.new_film();
+        assert_eq!((film.width, film.height), (4, 2));
+
+        // entirely outside: nothing to render.
+        camera.region = Some(region(20, 0, 4, 4, RegionOutput::Crop));
+        assert_eq!(camera.pixel_bounds(), (16..16, 0..4));
+        assert_eq!(camera.new_film().width, 0);
+
+        // a full-frame region still fills the whole film.
+        camera.region = Some(region(5, 2, 4, 3, RegionOutput::FullFrame));
+        assert_eq!(camera.pixel_bounds(), (5..9, 2..5));
+        let film = camera.new_film();
+        assert_eq!((film.width, film.height), (16, 8));
+        assert_eq!(camera.film_origin(), (0, 0));
+    }
+
+    #[test]
+    fn test_views_are_denoised_apart() {
+        // a flat but noisy view next to a brighter one.
+        let view = |levels: [f64; 2]| {
+            let mut film = Film::new(8, 4);
+            for p in film.pixels.iter_mut() {
+                for y in levels {
+                    p.add(Color::new(y, y, y), Some(&Aovs::default()));
+                }
+            }
+            film
+        };
+        let mut camera = test_camera(0);
+        camera.stereo = Some(Stereo {
+            interocular: 0.064,
+            convergence: 1.0,
+            layout: StereoLayout::SideBySide,
+        });
+        camera.initialize();
+
+        let denoised = camera.join_views(
+            &camera.denoise_views(&Denoiser::default(), &[view([0.0, 1.0]), view([0.5, 1.5])]),
+        );
+        assert_eq!((denoised.width, denoised.height), (16, 4));
+        for j in 0..4 {
+            assert!((denoised.get(7, j) - Color::new(0.5, 0.5, 0.5)).norm() < 1e-9);
+            assert!((denoised.get(8, j) - Color::new(1.0, 1.0, 1.0)).norm() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_effects_apply_to_each_view() {
+        let mut camera = test_camera(0);
+        camera.stereo = Some(Stereo {
+            interocular: 0.064,
+            convergence: 1.0,
+            layout: StereoLayout::SideBySide,
+        });
+        camera.effects.vignette = Some(Vignette::default());
+        camera.initialize();
+
+        let mut white = Image::new(16, 8);
+        white.pixels.fill(Color::new(1.0, 1.0, 1.0));
+        let joined = camera.apply_effects(&[white.clone(), white]);
+        assert_eq!((joined.width, joined.height), (32, 8));
+        // each eye is darkened towards its own corners, not the pair's.
+        for j in 0..8 {
+            for i in 0..16 {
+                assert_eq!(joined.get(i, j), joined.get(i + 16, j));
+            }
+        }
+        assert!(joined.get(15, 0).y() < joined.get(8, 4).y());
+    }
+
+    fn test_render(seed: u64, region: Option<Region>) -> Image {
+        let mut camera = test_camera(seed);
+        camera.region = region;
+        render_scene(&mut camera, &temp_stem("render"))
+    }
+
+    fn test_camera(seed: u64) -> Camera {
+        let mut camera = Camera::new(
+            2.0,
+            16,
+            90.0,
+            Point::new(0.0, 0.0, 0.0),
+            Point::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        camera.samples_per_pixel = 4;
+        camera.seed = seed;
+        camera
+    }
+
+    fn render_scene(camera: &mut Camera, stem: &str) -> Image {
+        let views = camera.render_film(&test_world(), stem).unwrap();
+        camera.join_views(&views).map(|p| p.mean())
+    }
+
+    fn test_world() -> HittableList {
+        let mut world = HittableList::new();
+        let ground = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let shiny = Rc::new(Metal::new(Color::new(0.8, 0.6, 0.2), 0.3));
+        world.add(Rc::new(Sphere::new(
+            Point::new(0.0, -100.5, -1.0),
+            100.0,
+            ground,
+        )));
+        world.add(Rc::new(Sphere::new(Point::new(0.0, 0.0, -1.0), 0.5, shiny)));
+        world
+    }
+
+    // Where a test's progressive renders may write, away from the real output.
+    fn temp_stem(name: &str) -> String {
+        let stem = format!("rsraycer_{}_{}", name, std::process::id());
+        std::env::temp_dir()
+            .join(stem)
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    fn progressive(samples_per_pass: u32, time_limit: Option<Duration>) -> Progressive {
+        Progressive {
+            samples_per_pass,
+            write_interval: Duration::MAX,
+            time_limit,
+            target_error: None,
+        }
+    }
+
+    #[test]
+    fn test_seeded_render_is_reproducible() {
+        let image = test_render(7, None);
+        assert_eq!(image, test_render(7, None));
+        assert_ne!(image, test_render(8, None));
+
+        // a crop visits fewer pixels, but those it does see the same samples.
+        let crop = test_render(
+            7,
+            Some(Region {
+                x: 5,
+                y: 2,
+                width: 4,
+                height: 3,
+                output: RegionOutput::Crop,
+            }),
+        );
+        for j in 0..crop.height {
+            for i in 0..crop.width {
+                assert_eq!(crop.get(i, j), image.get(i + 5, j + 2));
+            }
+        }
+    }
+
+    #[test]
+    fn test_progressive_matches_single_pass() {
+        let mut camera = test_camera(3);
+        camera.progressive = Some(progressive(3, None));
+        assert_eq!(
+            render_scene(&mut camera, &temp_stem("progressive")),
+            test_render(3, None)
+        );
+    }
+
+    #[test]
+    fn test_resume_matches_uninterrupted() {
+        let stem = temp_stem("resume");
+        let checkpoint = Some(Checkpoint {
+            interval: Duration::MAX,
+            resume: true,
+        });
+
+        // stopped by the time limit after its first pass.
+        let mut interrupted = test_camera(5);
+        interrupted.progressive = Some(progressive(2, Some(Duration::ZERO)));
+        interrupted.checkpoint = checkpoint;
+        render_scene(&mut interrupted, &stem);
+        let checkpoint_path = format!("{}.checkpoint", stem);
+        assert_eq!(Snapshot::read(&checkpoint_path).unwrap().samples, 2);
+
+        let mut resumed = test_camera(5);
+        resumed.progressive = Some(progressive(2, None));
+        resumed.checkpoint = checkpoint;
+        let image = render_scene(&mut resumed, &stem);
+        assert!(!Path::new(&checkpoint_path).exists());
+
+        assert_eq!(image, test_render(5, None));
+    }
+
+    #[test]
+    fn test_resume_rejects_other_settings() {
+        let stem = temp_stem("resume_other");
+        let checkpoint = Some(Checkpoint {
+            interval: Duration::MAX,
+            resume: true,
+        });
+        let mut interrupted = test_camera(5);
+        interrupted.progressive = Some(progressive(2, Some(Duration::ZERO)));
+        interrupted.checkpoint = checkpoint;
+        render_scene(&mut interrupted, &stem);
+
+        // same seed and size, but a different sampler or scene.
+        let mut resumed = test_camera(5);
+        resumed.progressive = Some(progressive(2, None));
+        resumed.checkpoint = checkpoint;
+        let mut other_sampler = resumed.clone();
+        other_sampler.sampler = SamplerKind::Halton;
+        let error = other_sampler.render_film(&test_world(), &stem).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut world = test_world();
+        world.add(Rc::new(Sphere::new(
+            Point::new(-1.5, 0.5, -1.5),
+            0.5,
+            Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+        let error = resumed.render_film(&world, &stem).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // a material no probe could tell apart, and a different pass size.
+        let mut world = HittableList::new();
+        let ground = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let shiny = Rc::new(Metal::new(Color::new(0.8, 0.6, 0.3), 0.3));
+        world.add(Rc::new(Sphere::new(
+            Point::new(0.0, -100.5, -1.0),
+            100.0,
+            ground,
+        )));
+        world.add(Rc::new(Sphere::new(Point::new(0.0, 0.0, -1.0), 0.5, shiny)));
+        let error = resumed.render_film(&world, &stem).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut other_passes = resumed.clone();
+        other_passes.progressive = Some(progressive(3, None));
+        let error = other_passes.render_film(&test_world(), &stem).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_file(format!("{}.checkpoint", stem)).unwrap();
+    }
+}
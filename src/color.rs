@@ -5,16 +5,18 @@ use std::fmt::Formatter;
 
 pub type Color = Vec3;
 
+/// Writes a display-encoded color, with components in [0, 1], as 8-bit values.
 impl Display for Color {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        let r = (linear_to_gamma(self.x()) * 255.999) as u8;
-        let g = (linear_to_gamma(self.y()) * 255.999) as u8;
-        let b = (linear_to_gamma(self.z()) * 255.999) as u8;
+        let r = (self.x().clamp(0.0, 1.0) * 255.999) as u8;
+        let g = (self.y().clamp(0.0, 1.0) * 255.999) as u8;
+        let b = (self.z().clamp(0.0, 1.0) * 255.999) as u8;
 
         write!(f, "{} {} {}", r, g, b)
     }
 }
 
-fn linear_to_gamma(component: f64) -> f64 {
-    if component > 0.0 { component.sqrt() } else { 0.0 }
+/// Relative luminance of a linear Rec. 709 / sRGB color.
+pub fn luminance(color: Color) -> f64 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
 }
@@ -0,0 +1,213 @@
+use crate::color::Color;
+use crate::color::luminance;
+use crate::film::Aovs;
+use crate::image::Image;
+
+// B3 spline, the smoothing kernel of the à-trous transform.
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// Stand-in for unknown (infinite) variance, large enough to switch the color
+// test off but finite so that it filters without producing NaNs.
+const MAX_VARIANCE: f64 = 1e12;
+
+/// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010). Each pass
+/// blurs with a 5x5 kernel spread twice as wide as the last, weighting
+/// neighbors down where the first-hit albedo, normal or depth differs, so
+/// that noise is smoothed away but object edges and texture stay sharp.
+/// Colors are compared relative to the pixel's noise level, as in SVGF
+/// (Schied et al. 2017), and the noise estimate is filtered along with them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Denoiser {
+    pub iterations: u32,
+    // Edge-stopping widths; smaller keeps more detail. Color differences are
+    // measured in standard deviations of the pixel's noise.
+    pub sigma_color: f64,
+    pub sigma_normal: f64,
+    pub sigma_albedo: f64,
+    // Relative to the pixel's depth.
+    pub sigma_depth: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            sigma_color: 4.0,
+            sigma_normal: 0.3,
+            sigma_albedo: 0.1,
+            sigma_depth: 0.05,
+        }
+    }
+}
+
+impl Denoiser {
+    /// Denoises `color` using the output variables of the same render and the
+    /// variance of each pixel's luminance estimate.
+    pub fn denoise(&self, color: &Image, variance: &Image<f64>, aovs: &Image<Aovs>) -> Image {
+        // filter the lighting alone, so texture detail isn't blurred with it.
+        let albedo = aovs.map(|a| {
+            Color::new(
+                demodulation(a.albedo.x()),
+                demodulation(a.albedo.y()),
+                demodulation(a.albedo.z()),
+            )
+        });
+        let mut lighting = color.clone();
+        let mut variance = variance.clone();
+        for ((l, v), a) in lighting
+            .pixels
+            .iter_mut()
+            .zip(variance.pixels.iter_mut())
+            .zip(&albedo.pixels)
+        {
+            *l = Color::new(l.x() / a.x(), l.y() / a.y(), l.z() / a.z());
+            *v = v.min(MAX_VARIANCE) / luminance(*a).powi(2);
+        }
+
+        for iteration in 0..self.iterations {
+            (lighting, variance) = self.pass(&lighting, &variance, aovs, 1 << iteration);
+        }
+
+        for (l, a) in lighting.pixels.iter_mut().zip(&albedo.pixels) {
+            *l *= *a;
+        }
+        lighting
+    }
+
+    // One filter pass with taps `step` pixels apart. Returns the filtered
+    // lighting and its variance.
+    fn pass(
+        &self,
+        lighting: &Image,
+        variance: &Image<f64>,
+        aovs: &Image<Aovs>,
+        step: i64,
+    ) -> (Image, Image<f64>) {
+        let mut out = Image::new(lighting.width, lighting.height);
+        let mut out_variance = Image::new(lighting.width, lighting.height);
+        let (width, height) = (lighting.width as i64, lighting.height as i64);
+
+        for j in 0..lighting.height {
+            for i in 0..lighting.width {
+                let c_p = lighting.get(i, j);
+                let f_p = aovs.get(i, j);
+                let color_scale = self.sigma_color * variance.get(i, j).max(0.0).sqrt() + 1e-6;
+
+                let mut sum = Color::new(0.0, 0.0, 0.0);
+                let mut variance_sum = 0.0;
+                let mut weight_sum = 0.0;
+                for (dy, ky) in KERNEL.iter().enumerate() {
+                    for (dx, kx) in KERNEL.iter().enumerate() {
+                        let x = i as i64 + (dx as i64 - 2) * step;
+                        let y = j as i64 + (dy as i64 - 2) * step;
+                        if x < 0 || y < 0 || x >= width || y >= height {
+                            continue;
+                        }
+                        let c_q = lighting.get(x as u32, y as u32);
+                        let f_q = aovs.get(x as u32, y as u32);
+
+                        let color_dist = luminance(c_p - c_q).abs() / color_scale;
+                        let normal_dist =
+                            (f_p.normal - f_q.normal).norm_sq() / self.sigma_normal.powi(2);
+                        let albedo_dist =
+                            (f_p.albedo - f_q.albedo).norm_sq() / self.sigma_albedo.powi(2);
+                        let depth_dist = (f_p.depth - f_q.depth).abs()
+                            / (self.sigma_depth * f_p.depth.max(1e-3));
+
+                        let weight =
+                            kx * ky * (-color_dist - normal_dist - albedo_dist - depth_dist).exp();
+                        sum += weight * c_q;
+                        variance_sum += weight * weight * variance.get(x as u32, y as u32);
+                        weight_sum += weight;
+                    }
+                }
+                out.set(i, j, sum / weight_sum);
+                out_variance.set(i, j, variance_sum / (weight_sum * weight_sum));
+            }
+        }
+
+        (out, out_variance)
+    }
+}
+
+// Albedo to divide the lighting by; black (the sky, or absorbed paths) is
+// left alone.
+fn demodulation(albedo: f64) -> f64 {
+    if albedo < 1e-3 { 1.0 } else { albedo }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Vec3;
+
+    fn noisy(i: u32, j: u32) -> f64 {
+        // a fixed pattern standing in for noise.
+        if (i * 7 + j * 13) % 5 < 2 { 0.8 } else { 0.2 }
+    }
+
+    #[test]
+    fn test_denoise_smooths_flat_area() {
+        let (width, height) = (16, 16);
+        let mut color = Image::new(width, height);
+        // standard deviation 0.3, about the spread of the pattern.
+        let mut variance = Image::new(width, height);
+        variance.pixels.fill(0.09);
+        let mut aovs: Image<Aovs> = Image::new(width, height);
+        for j in 0..height {
+            for i in 0..width {
+                let v = noisy(i, j);
+                color.set(i, j, Color::new(v, v, v));
+                aovs.set(
+                    i,
+                    j,
+                    Aovs {
+                        depth: 1.0,
+                        normal: Vec3::new(0.0, 0.0, 1.0),
+                        albedo: Color::new(0.5, 0.5, 0.5),
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+
+        let denoised = Denoiser::default().denoise(&color, &variance, &aovs);
+        let spread = |image: &Image| {
+            let values: Vec<f64> = image.pixels.iter().map(|c| c.x()).collect();
+            let max = values.iter().cloned().fold(f64::MIN, f64::max);
+            let min = values.iter().cloned().fold(f64::MAX, f64::min);
+            max - min
+        };
+        assert!(spread(&denoised) < 0.5 * spread(&color));
+    }
+
+    #[test]
+    fn test_denoise_keeps_edges() {
+        // two noiseless objects at different depths, each flat in color.
+        let (width, height) = (16, 8);
+        let mut color = Image::new(width, height);
+        let variance = Image::new(width, height);
+        let mut aovs: Image<Aovs> = Image::new(width, height);
+        for j in 0..height {
+            for i in 0..width {
+                let left = i < width / 2;
+                let v = if left { 0.1 } else { 0.9 };
+                color.set(i, j, Color::new(v, v, v));
+                aovs.set(
+                    i,
+                    j,
+                    Aovs {
+                        depth: if left { 1.0 } else { 10.0 },
+                        normal: Vec3::new(0.0, 0.0, 1.0),
+                        albedo: Color::new(1.0, 1.0, 1.0),
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+
+        let denoised = Denoiser::default().denoise(&color, &variance, &aovs);
+        assert!((denoised.get(width / 2 - 1, 4).x() - 0.1).abs() < 1e-3);
+        assert!((denoised.get(width / 2, 4).x() - 0.9).abs() < 1e-3);
+    }
+}
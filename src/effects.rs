@@ -0,0 +1,299 @@
+use crate::color::Color;
+use crate::color::luminance;
+use crate::image::Image;
+
+/// Glow around bright areas: everything above the threshold is blurred with
+/// several Gaussians, each half as wide as the last, and added back.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Bloom {
+    // Luminance above which pixels bloom.
+    pub threshold: f64,
+    // Fraction of the bright light spread into the glow.
+    pub intensity: f64,
+    // Standard deviation of the widest blur, in pixels; 0 turns bloom off.
+    pub radius: f64,
+    pub levels: u32,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            intensity: 0.2,
+            radius: 16.0,
+            levels: 4,
+        }
+    }
+}
+
+/// Star-shaped streaks from highlights, like diffraction from aperture
+/// blades.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Glare {
+    pub threshold: f64,
+    pub intensity: f64,
+    // Number of arms of the star, evenly spaced.
+    pub points: u32,
+    // Rotation of the first arm from horizontal, in degrees.
+    pub angle: f64,
+    // Length of each arm, in pixels.
+    pub length: f64,
+}
+
+impl Default for Glare {
+    fn default() -> Self {
+        Self {
+            threshold: 2.0,
+            intensity: 0.1,
+            points: 6,
+            angle: 15.0,
+            length: 48.0,
+        }
+    }
+}
+
+/// Darkening towards the corners of the frame.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Vignette {
+    // Darkening in the corners; 1 is black.
+    pub strength: f64,
+    // Distance from the center where the darkening starts, as a fraction of
+    // the distance to the corners; from 1 on, nothing is darkened.
+    pub start: f64,
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Self {
+            strength: 0.4,
+            start: 0.3,
+        }
+    }
+}
+
+/// Image-space effects, applied to the linear image before tone mapping.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Effects {
+    pub bloom: Option<Bloom>,
+    pub glare: Option<Glare>,
+    pub vignette: Option<Vignette>,
+}
+
+impl Effects {
+    /// Applies the effects to `image`, which covers the part of a frame
+    /// `frame` pixels in size starting at `origin`; a crop is vignetted as it
+    /// would be in the whole frame.
+    pub fn apply(&self, image: &Image, origin: (u32, u32), frame: (u32, u32)) -> Image {
+        let mut out = image.clone();
+
+        // both glows start from the original image, so they don't feed each
+        // other.
+        if let Some(bloom) = self.bloom
+            && bloom.radius > 0.0
+        {
+            let bright = bright_pass(image, bloom.threshold);
+            let levels = bloom.levels.max(1);
+            for level in 0..levels {
+                let sigma = bloom.radius / 2f64.powi(level as i32);
+                let blurred = gaussian_blur(&bright, sigma);
+                add(&mut out, &blurred, bloom.intensity / levels as f64);
+            }
+            add(&mut out, &bright, -bloom.intensity);
+        }
+
+        if let Some(glare) = self.glare {
+            let bright = bright_pass(image, glare.threshold);
+            add(&mut out, &streaks(&bright, &glare), glare.intensity);
+            add(&mut out, &bright, -glare.intensity);
+        }
+
+        if let Some(vignette) = self.vignette
+            && vignette.start < 1.0
+        {
+            let (cx, cy) = (frame.0 as f64 / 2.0, frame.1 as f64 / 2.0);
+            let corner = cx.hypot(cy);
+            for j in 0..out.height {
+                for i in 0..out.width {
+                    let x = (origin.0 + i) as f64 + 0.5 - cx;
+                    let y = (origin.1 + j) as f64 + 0.5 - cy;
+                    let r = x.hypot(y) / corner;
+                    let t = ((r - vignette.start) / (1.0 - vignette.start)).clamp(0.0, 1.0);
+                    let falloff = t * t * (3.0 - 2.0 * t);
+                    out.set(i, j, (1.0 - vignette.strength * falloff) * out.get(i, j));
+                }
+            }
+        }
+
+        out
+    }
+}
+
+// The part of each pixel above `threshold` in luminance, keeping its hue.
+fn bright_pass(image: &Image, threshold: f64) -> Image {
+    image.map(|c| {
+        let y = luminance(c);
+        if y > threshold {
+            c * ((y - threshold) / y)
+        } else {
+            Color::default()
+        }
+    })
+}
+
+fn add(image: &mut Image, other: &Image, scale: f64) {
+    for (p, q) in image.pixels.iter_mut().zip(&other.pixels) {
+        *p += scale * *q;
+    }
+}
+
+// Separable Gaussian blur; the image is extended by its edge pixels.
+fn gaussian_blur(image: &Image, sigma: f64) -> Image {
+    let radius = (3.0 * sigma).ceil() as i64;
+    let mut kernel: Vec<f64> = (-radius..=radius)
+        .map(|x| (-(x * x) as f64 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f64 = kernel.iter().sum();
+    for k in kernel.iter_mut() {
+        *k /= total;
+    }
+
+    let (width, height) = (image.width as i64, image.height as i64);
+    let blur = |image: &Image, dx: i64, dy: i64| {
+        let mut out = Image::new(image.width, image.height);
+        for j in 0..height {
+            for i in 0..width {
+                let mut sum = Color::default();
+                for (k, weight) in kernel.iter().enumerate() {
+                    let offset = k as i64 - radius;
+                    let x = (i + offset * dx).clamp(0, width - 1);
+                    let y = (j + offset * dy).clamp(0, height - 1);
+                    sum += *weight * image.get(x as u32, y as u32);
+                }
+                out.set(i as u32, j as u32, sum);
+            }
+        }
+        out
+    };
+    blur(&blur(image, 1, 0), 0, 1)
+}
+
+// Smears each pixel along the arms of the star, fading exponentially. Each
+// arm carries an equal share of the light.
+fn streaks(bright: &Image, glare: &Glare) -> Image {
+    let mut out = Image::new(bright.width, bright.height);
+    let points = glare.points.max(1);
+    let steps = glare.length.max(1.0).ceil() as u32;
+    let weights: Vec<f64> = (0..=steps)
+        .map(|k| (-4.0 * k as f64 / glare.length.max(1.0)).exp())
+        .collect();
+    let total: f64 = weights.iter().sum::<f64>() * points as f64;
+
+    for arm in 0..points {
+        let theta = glare.angle.to_radians() + std::f64::consts::TAU * arm as f64 / points as f64;
+        let (sin, cos) = theta.sin_cos();
+        for j in 0..bright.height {
+            for i in 0..bright.width {
+                let mut sum = Color::default();
+                for (k, weight) in weights.iter().enumerate() {
+                    // gather from the pixels this one lies on the arm of.
+                    let x = (i as f64 - k as f64 * cos).round();
+                    let y = (j as f64 - k as f64 * sin).round();
+                    if x < 0.0 || y < 0.0 || x >= bright.width as f64 || y >= bright.height as f64 {
+                        break;
+                    }
+                    sum += *weight * bright.get(x as u32, y as u32);
+                }
+                out.set(i, j, out.get(i, j) + sum / total);
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn highlight() -> Image {
+        let mut image = Image::new(33, 33);
+        image.pixels.fill(Color::new(0.5, 0.5, 0.5));
+        image.set(16, 16, Color::new(100.0, 100.0, 100.0));
+        image
+    }
+
+    #[test]
+    fn test_glows_conserve_light() {
+        let image = highlight();
+        let total = |image: &Image| image.pixels.iter().map(|c| c.y()).sum::<f64>();
+
+        let bloom = Effects {
+            bloom: Some(Bloom {
+                radius: 2.0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+        .apply(&image, (0, 0), (33, 33));
+        assert!((total(&bloom) - total(&image)).abs() < 1e-6 * total(&image));
+        assert!(bloom.get(20, 16).y() > 0.5);
+        assert!(bloom.get(16, 16).y() < 100.0);
+        // pixels below the threshold don't glow.
+        assert_eq!(bloom.get(0, 0), image.get(0, 0));
+
+        // a four-point star lights up the axes but not the diagonals.
+        let glare = Effects {
+            glare: Some(Glare {
+                points: 4,
+                angle: 0.0,
+                length: 8.0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+        .apply(&image, (0, 0), (33, 33));
+        assert!((total(&glare) - total(&image)).abs() < 1e-6 * total(&image));
+        assert!(glare.get(16, 20).y() > 0.5);
+        assert_eq!(glare.get(20, 20), image.get(20, 20));
+    }
+
+    #[test]
+    fn test_vignette() {
+        let mut image = Image::new(32, 16);
+        image.pixels.fill(Color::new(1.0, 1.0, 1.0));
+        let effects = Effects {
+            vignette: Some(Vignette::default()),
+            ..Default::default()
+        };
+        let vignetted = effects.apply(&image, (0, 0), (32, 16));
+        assert_eq!(vignetted.get(16, 8), image.get(16, 8));
+        assert!(vignetted.get(0, 0).y() < 0.7);
+        assert!(vignetted.get(0, 0).y() > 0.6);
+
+        // a crop is darkened where it sits in the frame.
+        let mut crop = Image::new(4, 3);
+        crop.pixels.fill(Color::new(1.0, 1.0, 1.0));
+        let crop = effects.apply(&crop, (2, 1), (32, 16));
+        for j in 0..3 {
+            for i in 0..4 {
+                assert_eq!(crop.get(i, j), vignetted.get(i + 2, j + 1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_degenerate_settings_change_nothing() {
+        let image = highlight();
+        let effects = Effects {
+            bloom: Some(Bloom {
+                radius: 0.0,
+                ..Default::default()
+            }),
+            vignette: Some(Vignette {
+                start: 1.0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(effects.apply(&image, (0, 0), (33, 33)), image);
+    }
+}
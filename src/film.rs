@@ -0,0 +1,374 @@
+use crate::color::Color;
+use crate::color::luminance;
+use crate::image::Image;
+use crate::vec3::Point;
+use crate::vec3::Vec3;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// The last byte is the format version.
+const SNAPSHOT_MAGIC: &[u8; 8] = b"RSRCKPT2";
+const AOV_CHANNELS: usize = 19;
+
+/// Accumulated samples for every pixel of a render.
+pub type Film = Image<PixelStats>;
+
+/// Arbitrary output variables: what a camera sample saw besides its color,
+/// for compositing and denoising. Surface values come from the first hit and
+/// are zero where the sample saw the sky.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Aovs {
+    // Distance from the camera.
+    pub depth: f64,
+    // World-space shading normal, facing out of the object.
+    pub normal: Vec3,
+    pub albedo: Color,
+    pub position: Point,
+    // Light that reached the camera after exactly one bounce.
+    pub direct: Color,
+    // Light that bounced more than once.
+    pub indirect: Color,
+    // Light seen directly, i.e. the sky behind the image.
+    pub emission: Color,
+    // IDs are zero for the sky. Pixels keep the IDs of their first sample.
+    pub object_id: u32,
+    pub material_id: u32,
+}
+
+impl Aovs {
+    // Adds the float channels of `other`; the IDs stay.
+    fn accumulate(&mut self, other: &Aovs) {
+        self.depth += other.depth;
+        self.normal += other.normal;
+        self.albedo += other.albedo;
+        self.position += other.position;
+        self.direct += other.direct;
+        self.indirect += other.indirect;
+        self.emission += other.emission;
+    }
+
+    // The float channels in a fixed order, for saving.
+    fn channels(&self) -> [f64; AOV_CHANNELS] {
+        let mut channels = [0.0; AOV_CHANNELS];
+        channels[0] = self.depth;
+        let vectors = [
+            self.normal,
+            self.albedo,
+            self.position,
+            self.direct,
+            self.indirect,
+            self.emission,
+        ];
+        for (k, v) in vectors.iter().enumerate() {
+            for i in 0..3 {
+                channels[1 + 3 * k + i] = v[i];
+            }
+        }
+        channels
+    }
+
+    fn from_channels(channels: [f64; AOV_CHANNELS], object_id: u32, material_id: u32) -> Self {
+        let vector = |k: usize| {
+            Vec3::new(
+                channels[1 + 3 * k],
+                channels[2 + 3 * k],
+                channels[3 + 3 * k],
+            )
+        };
+        Self {
+            depth: channels[0],
+            normal: vector(0),
+            albedo: vector(1),
+            position: vector(2),
+            direct: vector(3),
+            indirect: vector(4),
+            emission: vector(5),
+            object_id,
+            material_id,
+        }
+    }
+}
+
+/// Running sum of the samples taken in one pixel, plus the mean and variance
+/// of their luminance (Welford's algorithm) for judging convergence.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct PixelStats {
+    pub sum: Color,
+    pub count: u32,
+    mean: f64,
+    m2: f64,
+    // Only kept when the render's output variables are needed.
+    aov_sum: Option<Aovs>,
+}
+
+impl PixelStats {
+    /// Adds a sample, with its output variables if they are being kept. A
+    /// render should pass them for every sample or for none.
+    pub fn add(&mut self, sample: Color, aovs: Option<&Aovs>) {
+        if let Some(aovs) = aovs {
+            match &mut self.aov_sum {
+                Some(sum) => sum.accumulate(aovs),
+                None => self.aov_sum = Some(*aovs),
+            }
+        }
+
+        self.sum += sample;
+        self.count += 1;
+
+        let y = luminance(sample);
+        let delta = y - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (y - self.mean);
+    }
+
+    /// The pixel's estimate; black if no samples have been taken.
+    pub fn mean(&self) -> Color {
+        if self.count == 0 {
+            return Color::default();
+        }
+        self.sum / self.count as f64
+    }
+
+    /// The average output variables of the pixel's samples, if they were
+    /// kept.
+    pub fn aovs(&self) -> Option<Aovs> {
+        let sum = self.aov_sum?;
+        let mut channels = sum.channels();
+        for c in channels.iter_mut() {
+            *c /= self.count.max(1) as f64;
+        }
+        Some(Aovs::from_channels(
+            channels,
+            sum.object_id,
+            sum.material_id,
+        ))
+    }
+
+    /// Variance of the mean luminance, i.e. the squared standard error;
+    /// infinite until there are two samples to estimate it from.
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        self.m2 / (self.count - 1) as f64 / self.count as f64
+    }
+
+    /// Half-width of the 95% confidence interval of the mean luminance,
+    /// relative to the luminance itself. Dark pixels are judged against a
+    /// floor so they don't chase noise that can't be seen.
+    pub fn relative_error(&self) -> f64 {
+        1.96 * self.variance().sqrt() / self.mean.abs().max(0.01)
+    }
+}
+
+/// The state of an unfinished render, for resuming it later. Samplers are
+/// stateless, so the per-pixel sample counts double as the random state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub seed: u64,
+    // Hash of the settings and scene the render was started with.
+    pub fingerprint: u64,
+    // Samples per pixel taken so far.
+    pub samples: u32,
+    // One film per view (two when rendering stereo).
+    pub views: Vec<Film>,
+}
+
+impl Snapshot {
+    /// Serializes to a little-endian binary format. Floats are stored bit for
+    /// bit, so a resumed render continues exactly where this one left off.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = SNAPSHOT_MAGIC.to_vec();
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&self.fingerprint.to_le_bytes());
+        bytes.extend_from_slice(&self.samples.to_le_bytes());
+        bytes.extend_from_slice(&(self.views.len() as u32).to_le_bytes());
+        for view in &self.views {
+            bytes.extend_from_slice(&view.width.to_le_bytes());
+            bytes.extend_from_slice(&view.height.to_le_bytes());
+            for p in &view.pixels {
+                for value in [p.sum.x(), p.sum.y(), p.sum.z(), p.mean, p.m2] {
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+                bytes.extend_from_slice(&p.count.to_le_bytes());
+                bytes.push(p.aov_sum.is_some() as u8);
+                if let Some(aov_sum) = p.aov_sum {
+                    for value in aov_sum.channels() {
+                        bytes.extend_from_slice(&value.to_le_bytes());
+                    }
+                    bytes.extend_from_slice(&aov_sum.object_id.to_le_bytes());
+                    bytes.extend_from_slice(&aov_sum.material_id.to_le_bytes());
+                }
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader { bytes, pos: 0 };
+        let magic = reader.take(SNAPSHOT_MAGIC.len())?;
+        if magic[..7] != SNAPSHOT_MAGIC[..7] {
+            return Err(invalid("not a checkpoint file"));
+        }
+        if magic != SNAPSHOT_MAGIC {
+            return Err(invalid(
+                "checkpoint was written by another version; delete it to start over",
+            ));
+        }
+        let seed = reader.u64()?;
+        let fingerprint = reader.u64()?;
+        let samples = reader.u32()?;
+
+        let view_count = reader.u32()?;
+        let mut views = Vec::new();
+        for _ in 0..view_count {
+            let width = reader.u32()?;
+            let height = reader.u32()?;
+            let mut pixels = Vec::new();
+            for _ in 0..width as u64 * height as u64 {
+                let sum = Vec3::new(reader.f64()?, reader.f64()?, reader.f64()?);
+                let (mean, m2) = (reader.f64()?, reader.f64()?);
+                let count = reader.u32()?;
+                let aov_sum = match reader.u8()? {
+                    0 => None,
+                    _ => {
+                        let mut channels = [0.0; AOV_CHANNELS];
+                        for c in channels.iter_mut() {
+                            *c = reader.f64()?;
+                        }
+                        Some(Aovs::from_channels(channels, reader.u32()?, reader.u32()?))
+                    }
+                };
+                pixels.push(PixelStats {
+                    sum,
+                    count,
+                    mean,
+                    m2,
+                    aov_sum,
+                });
+            }
+            views.push(Film {
+                width,
+                height,
+                pixels,
+            });
+        }
+
+        Ok(Self {
+            seed,
+            fingerprint,
+            samples,
+            views,
+        })
+    }
+
+    /// Writes through a temporary file, so a crash while saving leaves the
+    /// previous checkpoint intact.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, self.to_bytes())?;
+        fs::rename(temp, path)
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let slice = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| invalid("truncated checkpoint file"))?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn f64(&mut self) -> io::Result<f64> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pixel_stats() {
+        let mut stats = PixelStats::default();
+        assert_eq!(stats.relative_error(), f64::INFINITY);
+
+        for y in [0.2, 0.4, 0.6, 0.8] {
+            stats.add(Color::new(y, y, y), None);
+        }
+        assert!((stats.mean().y() - 0.5).abs() < 1e-12);
+        assert_eq!(stats.aovs(), None);
+
+        // sample variance 1/15, over four samples.
+        let expected = 1.96 * (1.0 / 60.0f64).sqrt() / 0.5;
+        assert!((stats.relative_error() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut view = Film::new(2, 1);
+        let mut stats = PixelStats::default();
+        let aovs = Aovs {
+            depth: 2.5,
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            object_id: 3,
+            material_id: 1,
+            ..Default::default()
+        };
+        stats.add(Color::new(0.1, 0.2, 0.3), Some(&aovs));
+        stats.add(Color::new(1.0 / 3.0, 0.0, 7.5), Some(&Aovs::default()));
+        assert_eq!(stats.aovs().unwrap().depth, 1.25);
+        assert_eq!(stats.aovs().unwrap().object_id, 3);
+        view.set(1, 0, stats);
+        // a pixel without output variables.
+        let mut plain = PixelStats::default();
+        plain.add(Color::new(0.5, 0.5, 0.5), None);
+        view.set(0, 0, plain);
+
+        let snapshot = Snapshot {
+            seed: 42,
+            fingerprint: 0x1234_5678_9abc_def0,
+            samples: 2,
+            views: vec![view],
+        };
+        let bytes = snapshot.to_bytes();
+        assert_eq!(Snapshot::from_bytes(&bytes).unwrap(), snapshot);
+        assert!(Snapshot::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        // files from before the current layout are turned away up front.
+        let mut old = bytes.clone();
+        old[..8].copy_from_slice(b"RSRCKPT1");
+        let error = Snapshot::from_bytes(&old).unwrap_err();
+        assert!(error.to_string().contains("another version"));
+    }
+}
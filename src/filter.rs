@@ -0,0 +1,191 @@
+use crate::vec3::Vec3;
+
+// Bins per axis in the tabulated filter.
+const TABLE_SIZE: usize = 64;
+
+/// Pixel reconstruction filter. Radii are in pixels; all filters are
+/// separable products of a 1D profile in x and y.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Filter {
+    Box { radius: f64 },
+    Tent { radius: f64 },
+    // Gaussian shifted down to reach zero at the radius.
+    Gaussian { radius: f64, sigma: f64 },
+    // B = C = 1/3 is the recommended compromise between ringing and blur.
+    Mitchell { radius: f64, b: f64, c: f64 },
+    // Windowed sinc with `tau` lobes.
+    Lanczos { radius: f64, tau: f64 },
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::Box { radius: 0.5 }
+    }
+}
+
+impl Filter {
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius, .. } => radius,
+        }
+    }
+
+    /// The 1D filter profile at offset `x` from the pixel center.
+    pub fn evaluate(&self, x: f64) -> f64 {
+        let x = x.abs();
+        if x > self.radius() {
+            return 0.0;
+        }
+
+        match *self {
+            Filter::Box { .. } => 1.0,
+            Filter::Tent { radius } => radius - x,
+            Filter::Gaussian { radius, sigma } => {
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            }
+            Filter::Mitchell { radius, b, c } => {
+                // the cubic is defined over [-2, 2].
+                let x = 2.0 * x / radius;
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x.powi(2)
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                } else {
+                    ((-b - 6.0 * c) * x.powi(3)
+                        + (6.0 * b + 30.0 * c) * x.powi(2)
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                }
+            }
+            Filter::Lanczos { tau, .. } => sinc(x) * sinc(x / tau),
+        }
+    }
+}
+
+/// Filter importance sampling: pixel sample offsets are drawn in proportion to
+/// the magnitude of the filter, so each sample only carries the filter's sign
+/// and a plain average of the weighted samples reconstructs the pixel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterSampler {
+    radius: f64,
+    values: Vec<f64>,
+    // Normalized running sum of |values|, one entry per bin.
+    cdf: Vec<f64>,
+    // Integral of |f| over the integral of f.
+    weight: f64,
+}
+
+impl Default for FilterSampler {
+    fn default() -> Self {
+        Self::new(&Filter::default())
+    }
+}
+
+impl FilterSampler {
+    pub fn new(filter: &Filter) -> Self {
+        let radius = filter.radius();
+        let bin_width = 2.0 * radius / TABLE_SIZE as f64;
+        let values: Vec<f64> = (0..TABLE_SIZE)
+            .map(|i| filter.evaluate(-radius + (i as f64 + 0.5) * bin_width))
+            .collect();
+
+        let mut cdf = Vec::with_capacity(TABLE_SIZE);
+        let mut abs_sum = 0.0;
+        for value in &values {
+            abs_sum += value.abs();
+            cdf.push(abs_sum);
+        }
+        for c in cdf.iter_mut() {
+            *c /= abs_sum;
+        }
+        let sum: f64 = values.iter().sum();
+
+        Self {
+            radius,
+            values,
+            cdf,
+            weight: abs_sum / sum,
+        }
+    }
+
+    /// Maps two uniform numbers to an offset from the pixel center and the
+    /// weight of a sample taken there.
+    pub fn sample(&self, u: [f64; 2]) -> (Vec3, f64) {
+        let (x, wx) = self.sample_1d(u[0]);
+        let (y, wy) = self.sample_1d(u[1]);
+        (Vec3::new(x, y, 0.0), wx * wy)
+    }
+
+    fn sample_1d(&self, u: f64) -> (f64, f64) {
+        let bin = self.cdf.partition_point(|&c| c <= u).min(TABLE_SIZE - 1);
+        let lo = if bin == 0 { 0.0 } else { self.cdf[bin - 1] };
+        let within = ((u - lo) / (self.cdf[bin] - lo)).clamp(0.0, 1.0);
+
+        let bin_width = 2.0 * self.radius / TABLE_SIZE as f64;
+        let x = -self.radius + (bin as f64 + within) * bin_width;
+        (x, self.values[bin].signum() * self.weight)
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    let x = std::f64::consts::PI * x;
+    x.sin() / x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_box_sampling() {
+        let sampler = FilterSampler::default();
+        for u in [0.0, 0.3, 0.999] {
+            let (offset, weight) = sampler.sample([u, 1.0 - u]);
+            assert!(offset.x().abs() <= 0.5 && offset.y().abs() <= 0.5);
+            assert!((weight - 1.0).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_weights_average_to_one() {
+        let filters = [
+            Filter::Tent { radius: 1.0 },
+            Filter::Gaussian {
+                radius: 1.5,
+                sigma: 0.5,
+            },
+            Filter::Mitchell {
+                radius: 2.0,
+                b: 1.0 / 3.0,
+                c: 1.0 / 3.0,
+            },
+            Filter::Lanczos {
+                radius: 3.0,
+                tau: 3.0,
+            },
+        ];
+
+        let n = 1000;
+        for filter in filters {
+            let sampler = FilterSampler::new(&filter);
+            let mut total = 0.0;
+            for i in 0..n {
+                let u = (i as f64 + 0.5) / n as f64;
+                let (x, weight) = sampler.sample_1d(u);
+                assert!(x.abs() <= filter.radius());
+                total += weight;
+            }
+            assert!((total / n as f64 - 1.0).abs() < 1e-2);
+        }
+    }
+}
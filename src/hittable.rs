@@ -1,17 +1,40 @@
 use crate::ray::Ray;
 use crate::vec3::Point;
 use crate::vec3::Vec3;
+use crate::vec3::cross;
 use crate::vec3::dot;
 use crate::interval::Interval;
 use crate::material::Material;
+use std::fmt;
 use std::rc::Rc;
 
+#[derive(Clone)]
 pub struct Hit {
     pub p: Point,
+    // Shading normal, facing against the incoming ray. Materials may perturb it.
     pub normal: Vec3,
+    // True surface normal, facing the same side as `normal`.
+    pub geometric_normal: Vec3,
     pub t: f64,
     pub front_face: bool,
-    pub mat: Rc<dyn Material>
+    pub mat: Rc<dyn Material>,
+
+    // Surface parameterization and its partial derivatives.
+    pub u: f64,
+    pub v: f64,
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+
+    // Which object of the world was hit, counting from one; set by the lists
+    // holding it, so the outermost list has the last word.
+    pub object_id: u32,
+    // Which material was hit, counting from one in the order the world's
+    // materials were added; set by the lists, like `object_id`.
+    pub material_id: u32,
+    // Uniform number fixed per ray and hit, for random choices that the
+    // intersection test and scattering must agree on, e.g. which side of a
+    // `Mix` was hit.
+    pub choice: f64,
 }
 
 impl Hit {
@@ -26,13 +49,90 @@ impl Hit {
         Self {
             p,
             normal,
+            geometric_normal: normal,
             t,
             front_face,
-            mat
+            mat,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::default(),
+            dpdv: Vec3::default(),
+            object_id: 0,
+            material_id: 0,
+            choice: hash_float(&[r.origin, r.dir, p, Vec3::new(1.0, 0.0, 0.0)]),
+        }
+    }
+
+    pub fn with_uv(mut self, u: f64, v: f64, dpdu: Vec3, dpdv: Vec3) -> Self {
+        self.u = u;
+        self.v = v;
+        self.dpdu = dpdu;
+        self.dpdv = dpdv;
+        self
+    }
+
+    /// Unit tangent along increasing u, orthogonal to the shading normal. Falls
+    /// back to an arbitrary perpendicular where the parameterization degenerates.
+    pub fn tangent(&self) -> Vec3 {
+        let t = self.dpdu - dot(self.dpdu, self.normal) * self.normal;
+        if t.norm_sq() > 1e-12 {
+            return t.unit();
+        }
+        let axis = if self.normal.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        cross(axis, self.normal).unit()
+    }
+
+    /// Whether the material's opacity mask lets the ray pass through here.
+    /// Partial opacity is resolved stochastically, but deterministically per
+    /// ray and hit so repeated tests agree.
+    pub fn is_cut_out(&self, r: &Ray) -> bool {
+        let opacity = self.mat.opacity(self);
+        if opacity >= 1.0 {
+            return false;
+        }
+        if opacity <= 0.0 {
+            return true;
+        }
+        hash_float(&[r.origin, r.dir, self.p]) >= opacity
+    }
+
+    /// The normal as seen from outside the surface, regardless of which side
+    /// the ray came from.
+    pub fn outward_normal(&self) -> Vec3 {
+        if self.front_face {
+            self.normal
+        } else {
+            -self.normal
         }
     }
 }
 
-pub trait Hittable {
+// The debug output lists the object's parameters, so that checkpoints can
+// tell scenes apart.
+pub trait Hittable: fmt::Debug {
+    /// Returns the closest hit within `ray_t`. Primitives should skip hits that
+    /// are cut out by the material's opacity (see `Hit::is_cut_out`).
     fn hit(&self, r: &Ray, ray_t: Interval) -> Option<Hit>;
+
+    /// The materials the object is made of, each once, in a fixed order.
+    fn materials(&self) -> Vec<Rc<dyn Material>>;
+}
+
+// Hashes vectors to a float in [0, 1).
+fn hash_float(values: &[Vec3]) -> f64 {
+    let mut h: u64 = 0x9e37_79b9_7f4a_7c15;
+    for v in values {
+        for i in 0..3 {
+            h ^= v[i].to_bits();
+            h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+            h ^= h >> 33;
+        }
+    }
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^= h >> 33;
+    (h >> 11) as f64 / (1u64 << 53) as f64
 }
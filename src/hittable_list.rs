@@ -1,37 +1,73 @@
 use crate::hittable::Hit;
 use crate::hittable::Hittable;
 use crate::interval::Interval;
+use crate::material::Material;
 use crate::ray::Ray;
+use std::collections::HashMap;
+use std::fmt;
 use std::rc::Rc;
 
+#[derive(Default)]
 pub struct HittableList {
     objects: Vec<Rc<dyn Hittable>>,
+    // Distinct materials of the objects, in the order they were added.
+    materials: Vec<Rc<dyn Material>>,
+    material_ids: HashMap<*const (), u32>,
 }
 
 impl HittableList {
     pub fn new() -> Self {
         Self {
             objects: Vec::new(),
+            materials: Vec::new(),
+            material_ids: HashMap::new(),
         }
     }
 
     pub fn add(&mut self, object: Rc<dyn Hittable>) {
+        for mat in object.materials() {
+            let key = Rc::as_ptr(&mat) as *const ();
+            if !self.material_ids.contains_key(&key) {
+                self.materials.push(mat);
+                self.material_ids.insert(key, self.materials.len() as u32);
+            }
+        }
         self.objects.push(object);
     }
 }
 
+// Only the objects: the material numbering follows from them, and the map
+// behind it is keyed by address.
+impl fmt::Debug for HittableList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(&self.objects).finish()
+    }
+}
+
 impl Hittable for HittableList {
     fn hit(&self, r: &Ray, ray_t: Interval) -> Option<Hit> {
         let mut result = None;
         let mut current_max = ray_t.max;
 
-        for object in &self.objects {
-            if let Some(hit) = object.hit(r, Interval::new(ray_t.min, current_max)) {
+        for (index, object) in self.objects.iter().enumerate() {
+            // cut-out hits were already skipped by the object itself, in its
+            // own space.
+            if let Some(mut hit) = object.hit(r, Interval::new(ray_t.min, current_max)) {
                 current_max = hit.t;
+                hit.object_id = index as u32 + 1;
                 result = Some(hit);
             }
         }
 
+        if let Some(hit) = &mut result
+            && let Some(&id) = self.material_ids.get(&(Rc::as_ptr(&hit.mat) as *const ()))
+        {
+            hit.material_id = id;
+        }
         result
     }
+
+    fn materials(&self) -> Vec<Rc<dyn Material>> {
+        self.materials.clone()
+    }
 }
@@ -0,0 +1,164 @@
+use crate::color::Color;
+
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A framebuffer, stored row by row from the top-left pixel. Holds linear RGB
+/// unless another pixel type is given.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image<T = Color> {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<T>,
+}
+
+impl<T: Copy + Default> Image<T> {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![T::default(); (width * height) as usize],
+        }
+    }
+
+    pub fn get(&self, i: u32, j: u32) -> T {
+        self.pixels[(j * self.width + i) as usize]
+    }
+
+    pub fn set(&mut self, i: u32, j: u32, value: T) {
+        self.pixels[(j * self.width + i) as usize] = value;
+    }
+
+    pub fn map<U, F: Fn(T) -> U>(&self, f: F) -> Image<U> {
+        Image {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.iter().map(|&p| f(p)).collect(),
+        }
+    }
+
+    /// Places two images of the same size next to each other.
+    pub fn side_by_side(left: &Self, right: &Self) -> Self {
+        assert_eq!(
+            (left.width, left.height),
+            (right.width, right.height),
+            "side by side images differ in size"
+        );
+        let mut image = Self::new(left.width + right.width, left.height);
+        for j in 0..left.height {
+            for i in 0..left.width {
+                image.set(i, j, left.get(i, j));
+                image.set(i + left.width, j, right.get(i, j));
+            }
+        }
+        image
+    }
+
+    /// Stacks two images of the same size on top of each other.
+    pub fn over_under(top: &Self, bottom: &Self) -> Self {
+        assert_eq!(
+            (top.width, top.height),
+            (bottom.width, bottom.height),
+            "stacked images differ in size"
+        );
+        let mut pixels = top.pixels.clone();
+        pixels.extend_from_slice(&bottom.pixels);
+        Self {
+            width: top.width,
+            height: top.height + bottom.height,
+            pixels,
+        }
+    }
+}
+
+/// Pixel types that can be saved as a portable float map.
+pub trait PfmPixel {
+    // "PF" for color, "Pf" for greyscale.
+    const MAGIC: &'static str;
+
+    fn push_channels(&self, out: &mut Vec<u8>);
+}
+
+impl PfmPixel for Color {
+    const MAGIC: &'static str = "PF";
+
+    fn push_channels(&self, out: &mut Vec<u8>) {
+        for value in [self.x(), self.y(), self.z()] {
+            out.extend_from_slice(&(value as f32).to_le_bytes());
+        }
+    }
+}
+
+impl PfmPixel for f64 {
+    const MAGIC: &'static str = "Pf";
+
+    fn push_channels(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(*self as f32).to_le_bytes());
+    }
+}
+
+impl<T: Copy + Default + PfmPixel> Image<T> {
+    /// Linear, unclipped floats, little-endian, stored bottom row first.
+    pub fn to_pfm(&self) -> Vec<u8> {
+        let mut pfm = format!("{}\n{} {}\n-1.0\n", T::MAGIC, self.width, self.height).into_bytes();
+        for j in (0..self.height).rev() {
+            for i in 0..self.width {
+                self.get(i, j).push_channels(&mut pfm);
+            }
+        }
+        pfm
+    }
+
+    pub fn write_pfm<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_pfm())
+    }
+}
+
+impl Image {
+    /// Encodes display values in [0, 1] as an 8-bit PPM; linear renders go
+    /// through a `ToneMap` first.
+    pub fn to_ppm(&self) -> String {
+        let mut ppm = String::new();
+
+        writeln!(ppm, "P3").unwrap(); // magic number
+        writeln!(ppm, "{} {}", self.width, self.height).unwrap(); // width <SP> height
+        writeln!(ppm, "255").unwrap(); // maxval
+
+        for pixel in &self.pixels {
+            writeln!(ppm, "{}", pixel).unwrap();
+        }
+        ppm
+    }
+
+    pub fn write_ppm<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_ppm())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pfm_layout() {
+        let mut image: Image<f64> = Image::new(2, 2);
+        image.set(0, 1, 0.5);
+
+        let pfm = image.to_pfm();
+        let header = b"Pf\n2 2\n-1.0\n";
+        assert_eq!(&pfm[..header.len()], header);
+        assert_eq!(pfm.len(), header.len() + 4 * 4);
+        // the bottom row comes first.
+        assert_eq!(pfm[header.len()..header.len() + 4], 0.5f32.to_le_bytes());
+    }
+
+    #[test]
+    #[should_panic(expected = "differ in size")]
+    fn test_side_by_side_rejects_mismatched_sizes() {
+        let left: Image<f64> = Image::new(2, 2);
+        let right: Image<f64> = Image::new(3, 2);
+        Image::side_by_side(&left, &right);
+    }
+}
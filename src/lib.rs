@@ -0,0 +1,20 @@
+pub mod animation;
+pub mod aperture;
+pub mod camera;
+pub mod color;
+pub mod denoise;
+pub mod effects;
+pub mod film;
+pub mod filter;
+pub mod hittable;
+pub mod hittable_list;
+pub mod image;
+pub mod interval;
+pub mod material;
+pub mod ray;
+pub mod sampler;
+pub mod spectrum;
+pub mod sphere;
+pub mod texture;
+pub mod tonemap;
+pub mod vec3;
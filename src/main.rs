@@ -1,29 +1,29 @@
-use crate::color::Color;
-use crate::hittable_list::HittableList;
-use crate::material::Dielectric;
-use crate::material::Lambertian;
-use crate::material::Metal;
-use crate::sphere::Sphere;
-use crate::vec3::Point;
-use crate::vec3::Vec3;
-use crate::vec3::random;
 use rand::prelude::*;
+use rsraycer::camera::Camera;
+use rsraycer::color::Color;
+use rsraycer::hittable_list::HittableList;
+use rsraycer::material::Dielectric;
+use rsraycer::material::Lambertian;
+use rsraycer::material::Metal;
+use rsraycer::sphere::Sphere;
+use rsraycer::vec3::Point;
+use rsraycer::vec3::Vec3;
+use rsraycer::vec3::random;
+use std::env;
+use std::io;
 use std::rc::Rc;
 
-mod camera;
-mod color;
-mod hittable;
-mod hittable_list;
-mod interval;
-mod material;
-mod ray;
-mod sphere;
-mod vec3;
-
-fn main() {
+fn main() -> io::Result<()> {
     // Treat these as input parameters; height is derived from width and aspect
     let aspect: f64 = 16.0 / 9.0;
     let img_width: u32 = 1920;
+    // Seeds both the scene layout and the render; pass one on the command line
+    // for a different, but still reproducible, image.
+    let seed: u64 = env::args()
+        .nth(1)
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(0);
+    let mut rng = StdRng::seed_from_u64(seed);
 
     let ground = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
 
@@ -62,11 +62,11 @@ fn main() {
     // lots of random marbles checkerboarded around.
     for i in -11..11 {
         for j in -11..11 {
-            let mat = rand::rng().random::<f64>();
+            let mat = rng.random::<f64>();
             let center = Point::new(
-                i as f64 + 0.9 * rand::rng().random::<f64>(),
+                i as f64 + 0.9 * rng.random::<f64>(),
                 0.2,
-                j as f64 + 0.9 * rand::rng().random::<f64>(),
+                j as f64 + 0.9 * rng.random::<f64>(),
             );
 
             if (center - Point::new(4.0, 2.0, 0.0)).norm() > 2.2
@@ -75,13 +75,13 @@ fn main() {
             {
                 if mat < 0.8 {
                     // diffuse
-                    let albedo = random() * random();
+                    let albedo = random(&mut rng) * random(&mut rng);
                     let sphere_mat = Rc::new(Lambertian::new(albedo));
                     world.add(Rc::new(Sphere::new(center, 0.2, sphere_mat.clone())));
                 } else if mat < 0.95 {
                     // shiny
-                    let albedo = random() * random();
-                    let fuzz = rand::rng().random_range(0.0..0.5f64);
+                    let albedo = random(&mut rng) * random(&mut rng);
+                    let fuzz = rng.random_range(0.0..0.5f64);
                     let sphere_mat = Rc::new(Metal::new(albedo, fuzz));
                     world.add(Rc::new(Sphere::new(center, 0.2, sphere_mat.clone())));
                 } else {
@@ -93,7 +93,7 @@ fn main() {
         }
     }
 
-    let mut camera = camera::Camera::new(
+    let mut camera = Camera::new(
         aspect,
         img_width,
         30.0,
@@ -101,5 +101,6 @@ fn main() {
         Point::new(0.0, 0.0, -1.0),
         Vec3::new(0.0, 1.0, 0.0),
     );
-    camera.render(&world);
+    camera.seed = seed;
+    camera.render(&world)
 }
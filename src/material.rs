@@ -1,17 +1,57 @@
 use crate::color::Color;
+use crate::color::luminance;
 use crate::hittable::Hit;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::spectrum::D_LINE;
+use crate::texture::SolidColor;
+use crate::texture::Texture;
+use crate::vec3::Vec3;
+use crate::vec3::cross;
 use crate::vec3::dot;
-use crate::vec3::random_unit_vector;
 use crate::vec3::reflect;
 use crate::vec3::refract;
+use crate::vec3::sample_unit_vector;
 
-use rand::prelude::*;
+use std::f64::consts::PI;
+use std::fmt;
+use std::rc::Rc;
 
-pub trait Material {
-    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Color, Ray)>;
+// Like `Hittable`, materials describe their parameters in their debug output.
+pub trait Material: fmt::Debug {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)>;
+
+    // Whether the scattered direction depends on the ray's wavelength.
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+
+    // Probability that a ray stops at this hit rather than passing through it.
+    fn opacity(&self, _hit: &Hit) -> f64 {
+        1.0
+    }
+
+    // Scattering medium filling the object's interior, for subsurface materials.
+    fn medium(&self) -> Option<Medium> {
+        None
+    }
+
+    // Beer-Lambert absorption of the clear medium filling the object's
+    // interior, for materials that refract into one.
+    fn absorption(&self) -> Option<Color> {
+        None
+    }
+}
+
+/// Homogeneous participating medium, as extinction per unit distance and the
+/// fraction of extinction that scatters rather than absorbs.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Medium {
+    pub sigma_t: Color,
+    pub albedo: Color,
 }
 
+#[derive(Debug)]
 pub struct Lambertian {
     albedo: Color,
 }
@@ -23,48 +63,396 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _r_in: &Ray, hit: &Hit) -> Option<(Color, Ray)> {
-        let scatter_dir = hit.normal + random_unit_vector();
+    fn scatter(&self, r_in: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        let scatter_dir = hit.normal + sample_unit_vector(sampler.get_2d());
         let scattered_ray = if !scatter_dir.near_zero() {
             Ray::new(hit.p, scatter_dir)
         } else {
             Ray::new(hit.p, hit.normal)
         };
-        Some((self.albedo, scattered_ray))
+        Some((r_in.spectral(self.albedo), scattered_ray))
+    }
+}
+
+/// Rough diffuse reflection (Oren-Nayar) for clay, concrete and cloth.
+/// `sigma` is the standard deviation of the microfacet slope angle, in degrees;
+/// zero reduces to `Lambertian`.
+#[derive(Debug)]
+pub struct OrenNayar {
+    albedo: Color,
+    a: f64,
+    b: f64,
+}
+
+impl OrenNayar {
+    pub fn new(albedo: Color, sigma: f64) -> Self {
+        let sigma_sq = sigma.to_radians().powf(2.0);
+        Self {
+            albedo,
+            a: 1.0 - sigma_sq / (2.0 * (sigma_sq + 0.33)),
+            b: 0.45 * sigma_sq / (sigma_sq + 0.09),
+        }
+    }
+}
+
+impl Material for OrenNayar {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        let scatter_dir = hit.normal + sample_unit_vector(sampler.get_2d());
+        let scatter_dir = if !scatter_dir.near_zero() {
+            scatter_dir
+        } else {
+            hit.normal
+        };
+
+        // cosine-weighted sampling cancels the Lambertian part, leaving the
+        // Oren-Nayar factor as the weight.
+        let wo = -r_in.dir.unit();
+        let wi = scatter_dir.unit();
+        let cos_o = dot(wo, hit.normal).abs().min(1.0);
+        let cos_i = dot(wi, hit.normal).abs().min(1.0);
+        let sin_o = (1.0 - cos_o.powf(2.0)).sqrt();
+        let sin_i = (1.0 - cos_i.powf(2.0)).sqrt();
+
+        let max_cos = if sin_o > 1e-4 && sin_i > 1e-4 {
+            let wo_perp = (wo - dot(wo, hit.normal) * hit.normal).unit();
+            let wi_perp = (wi - dot(wi, hit.normal) * hit.normal).unit();
+            dot(wo_perp, wi_perp).max(0.0)
+        } else {
+            0.0
+        };
+        let (sin_alpha, tan_beta) = if cos_i > cos_o {
+            (sin_o, sin_i / cos_i)
+        } else {
+            (sin_i, sin_o / cos_o.max(1e-8))
+        };
+
+        let weight = self.a + self.b * max_cos * sin_alpha * tan_beta;
+        let scattered_ray = Ray::new(hit.p, scatter_dir);
+        Some((weight * r_in.spectral(self.albedo), scattered_ray))
+    }
+}
+
+/// Two-sided diffuse for thin sheets such as paper or lampshades: light is
+/// reflected diffusely with `reflectance` or passes to the other side,
+/// diffusely, with `transmittance`.
+#[derive(Debug)]
+pub struct DiffuseTransmission {
+    reflectance: Color,
+    transmittance: Color,
+}
+
+impl DiffuseTransmission {
+    pub fn new(reflectance: Color, transmittance: Color) -> Self {
+        Self {
+            reflectance,
+            transmittance,
+        }
+    }
+}
+
+impl Material for DiffuseTransmission {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        let r = luminance(self.reflectance).max(0.0);
+        let t = luminance(self.transmittance).max(0.0);
+        if r + t <= 0.0 {
+            return None;
+        }
+
+        // pick a side in proportion to how much light goes there.
+        let p_reflect = r / (r + t);
+        let (side, color, prob) = if sampler.get_1d() < p_reflect {
+            (hit.normal, self.reflectance, p_reflect)
+        } else {
+            (-hit.normal, self.transmittance, 1.0 - p_reflect)
+        };
+
+        let scatter_dir = side + sample_unit_vector(sampler.get_2d());
+        let scatter_dir = if !scatter_dir.near_zero() {
+            scatter_dir
+        } else {
+            side
+        };
+        let scattered_ray = Ray::new(hit.p, scatter_dir);
+        Some((r_in.spectral(color) / prob, scattered_ray))
     }
 }
 
+#[derive(Debug)]
 pub struct Metal {
     albedo: Color,
     fuzz: f64,
+    thin_film: Option<ThinFilm>,
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Self {
-        Self { albedo, fuzz }
+        Self {
+            albedo,
+            fuzz,
+            thin_film: None,
+        }
+    }
+
+    /// Coats the metal with a thin transparent film, e.g. an oxide layer.
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Color, Ray)> {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
         let reflected_dir = reflect(r_in.dir, hit.normal);
-        let reflected_dir = reflected_dir.unit() + (self.fuzz * random_unit_vector());
+        let reflected_dir =
+            reflected_dir.unit() + (self.fuzz * sample_unit_vector(sampler.get_2d()));
         let scattered_ray = Ray::new(hit.p, reflected_dir);
-        if dot(scattered_ray.dir, hit.normal) > 0.0 {
-            Some((self.albedo, scattered_ray))
+        if dot(scattered_ray.dir, hit.normal) <= 0.0 {
+            return None;
+        }
+
+        let albedo = r_in.spectral(self.albedo);
+        let attenuation = match &self.thin_film {
+            Some(film) => {
+                let cos_theta = dot(-r_in.dir.unit(), hit.normal).clamp(0.0, 1.0);
+                let lambda = r_in.channel_wavelengths();
+                Color::new(
+                    film.reflectance(cos_theta, lambda[0], Substrate::Conductor(albedo.x())),
+                    film.reflectance(cos_theta, lambda[1], Substrate::Conductor(albedo.y())),
+                    film.reflectance(cos_theta, lambda[2], Substrate::Conductor(albedo.z())),
+                )
+            }
+            None => albedo,
+        };
+        Some((attenuation, scattered_ray))
+    }
+}
+
+/// Anisotropic GGX conductor for brushed metal: roughness `alpha_x` runs along
+/// the surface tangent (the primitive's u direction) and `alpha_y` across it,
+/// with the tangent frame rotated by `rotation` degrees about the normal.
+#[derive(Debug)]
+pub struct AnisotropicMetal {
+    albedo: Color,
+    alpha_x: f64,
+    alpha_y: f64,
+    rotation: f64,
+}
+
+impl AnisotropicMetal {
+    pub fn new(albedo: Color, alpha_x: f64, alpha_y: f64, rotation: f64) -> Self {
+        Self {
+            albedo,
+            alpha_x: alpha_x.max(1e-4),
+            alpha_y: alpha_y.max(1e-4),
+            rotation,
+        }
+    }
+
+    // Smith's auxiliary function for the anisotropic GGX distribution.
+    fn lambda(&self, w: Vec3) -> f64 {
+        if w.z().abs() < 1e-12 {
+            return f64::INFINITY;
+        }
+        let tan_sq =
+            ((self.alpha_x * w.x()).powf(2.0) + (self.alpha_y * w.y()).powf(2.0)) / w.z().powf(2.0);
+        (-1.0 + (1.0 + tan_sq).sqrt()) / 2.0
+    }
+
+    // Samples a microfacet normal visible from `wo` (Heitz 2018), in the local
+    // frame where the surface normal is +z.
+    fn sample_visible_normal(&self, wo: Vec3, u: [f64; 2]) -> Vec3 {
+        let vh = Vec3::new(self.alpha_x * wo.x(), self.alpha_y * wo.y(), wo.z()).unit();
+        let len_sq = vh.x().powf(2.0) + vh.y().powf(2.0);
+        let t1 = if len_sq > 0.0 {
+            Vec3::new(-vh.y(), vh.x(), 0.0) / len_sq.sqrt()
         } else {
-            None
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = cross(vh, t1);
+
+        let r = u[0].sqrt();
+        let phi = 2.0 * PI * u[1];
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1.powf(2.0)).sqrt() + s * r * phi.sin();
+
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1.powf(2.0) - p2.powf(2.0)).max(0.0).sqrt() * vh;
+        Vec3::new(
+            self.alpha_x * nh.x(),
+            self.alpha_y * nh.y(),
+            nh.z().max(0.0),
+        )
+        .unit()
+    }
+}
+
+impl Material for AnisotropicMetal {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        let n = hit.normal;
+        let (sin_r, cos_r) = self.rotation.to_radians().sin_cos();
+        let t = cos_r * hit.tangent() + sin_r * cross(n, hit.tangent());
+        let b = cross(n, t);
+
+        let wo_world = -r_in.dir.unit();
+        let wo = Vec3::new(dot(wo_world, t), dot(wo_world, b), dot(wo_world, n));
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let m = self.sample_visible_normal(wo, sampler.get_2d());
+        let wi = 2.0 * dot(wo, m) * m - wo;
+        if wi.z() <= 0.0 {
+            return None;
+        }
+
+        // with visible-normal sampling the weight reduces to F * G2 / G1.
+        let lambda_o = self.lambda(wo);
+        let shadowing = (1.0 + lambda_o) / (1.0 + lambda_o + self.lambda(wi));
+        let f0 = r_in.spectral(self.albedo);
+        let fresnel = f0 + (Color::new(1.0, 1.0, 1.0) - f0) * (1.0 - dot(wo, m)).powf(5.0);
+
+        let wi_world = wi.x() * t + wi.y() * b + wi.z() * n;
+        Some((shadowing * fresnel, Ray::new(hit.p, wi_world)))
+    }
+}
+
+/// A thin transparent layer on a surface (soap bubbles, oil slicks, oxides)
+/// whose reflections interfere, tinting reflectance by wavelength. Thickness is
+/// in nanometers.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ThinFilm {
+    pub thickness: f64,
+    pub refraction_index: f64,
+}
+
+// What lies beneath a thin film.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Substrate {
+    Dielectric(f64),
+    // Normal-incidence reflectance; conductors are assumed to flip the phase.
+    Conductor(f64),
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f64, refraction_index: f64) -> Self {
+        Self {
+            thickness,
+            refraction_index,
+        }
+    }
+
+    // Unpolarized Airy reflectance of air / film / substrate at one wavelength.
+    fn reflectance(&self, cos_i: f64, wavelength: f64, substrate: Substrate) -> f64 {
+        let n_film = self.refraction_index;
+        let sin_sq_film = (1.0 - cos_i.powf(2.0)) / n_film.powf(2.0);
+        if sin_sq_film >= 1.0 {
+            return 1.0;
+        }
+        let cos_film = (1.0 - sin_sq_film).sqrt();
+
+        let (r12_s, r12_p) = fresnel_amplitudes(1.0, n_film, cos_i, cos_film);
+        let (r23_s, r23_p) = match substrate {
+            Substrate::Dielectric(n_sub) => {
+                let sin_sq_sub = sin_sq_film * (n_film / n_sub).powf(2.0);
+                if sin_sq_sub >= 1.0 {
+                    (1.0, 1.0)
+                } else {
+                    fresnel_amplitudes(n_film, n_sub, cos_film, (1.0 - sin_sq_sub).sqrt())
+                }
+            }
+            Substrate::Conductor(f0) => (-f0.sqrt(), -f0.sqrt()),
+        };
+
+        let phase = 4.0 * PI * n_film * self.thickness * cos_film / wavelength;
+        (airy(r12_s, r23_s, phase) + airy(r12_p, r23_p, phase)) / 2.0
+    }
+}
+
+// s and p amplitude reflection coefficients at an interface from n1 to n2.
+fn fresnel_amplitudes(n1: f64, n2: f64, cos_1: f64, cos_2: f64) -> (f64, f64) {
+    let s = (n1 * cos_1 - n2 * cos_2) / (n1 * cos_1 + n2 * cos_2);
+    let p = (n2 * cos_1 - n1 * cos_2) / (n2 * cos_1 + n1 * cos_2);
+    (s, p)
+}
+
+// Sums the multiple reflections inside a film with the given round-trip phase.
+fn airy(r12: f64, r23: f64, phase: f64) -> f64 {
+    let cross_term = 2.0 * r12 * r23 * phase.cos();
+    (r12.powf(2.0) + r23.powf(2.0) + cross_term) / (1.0 + (r12 * r23).powf(2.0) + cross_term)
+}
+
+/// Index of refraction, optionally varying with wavelength (in nanometers).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RefractiveIndex {
+    Constant(f64),
+    // n = a + b / lambda^2, lambda in micrometers.
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum(b_i lambda^2 / (lambda^2 - c_i)), lambda in micrometers.
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl RefractiveIndex {
+    pub fn at(&self, wavelength: f64) -> f64 {
+        let lambda_sq = (wavelength / 1000.0).powf(2.0);
+        match self {
+            Self::Constant(n) => *n,
+            Self::Cauchy { a, b } => a + b / lambda_sq,
+            Self::Sellmeier { b, c } => {
+                let sum: f64 = (0..3).map(|i| b[i] * lambda_sq / (lambda_sq - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
         }
     }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Self::Constant(_))
+    }
 }
 
+#[derive(Debug)]
 pub struct Dielectric {
-    refraction_index: f64,
+    refraction_index: RefractiveIndex,
+    // Beer-Lambert absorption coefficient per unit distance inside the medium.
+    absorption: Color,
+    thin_film: Option<ThinFilm>,
 }
 
 impl Dielectric {
     pub fn new(refraction_index: f64) -> Self {
-        Self { refraction_index }
+        Self::with_index(RefractiveIndex::Constant(refraction_index))
+    }
+
+    pub fn cauchy(a: f64, b: f64) -> Self {
+        Self::with_index(RefractiveIndex::Cauchy { a, b })
+    }
+
+    pub fn sellmeier(b: [f64; 3], c: [f64; 3]) -> Self {
+        Self::with_index(RefractiveIndex::Sellmeier { b, c })
+    }
+
+    fn with_index(refraction_index: RefractiveIndex) -> Self {
+        Self {
+            refraction_index,
+            absorption: Color::new(0.0, 0.0, 0.0),
+            thin_film: None,
+        }
+    }
+
+    /// Tints light by the distance it travels through the glass, e.g. an
+    /// absorption of `(0.0, 0.5, 1.0)` gives red glass that deepens with thickness.
+    pub fn with_absorption(mut self, absorption: Color) -> Self {
+        self.absorption = absorption;
+        self
+    }
+
+    /// Coats the outside of the glass with a thin film, e.g. a soap bubble.
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
+    fn substrate(&self, wavelength: f64) -> Substrate {
+        Substrate::Dielectric(self.refraction_index.at(wavelength))
     }
 
     fn reflectance(cosine: f64, refractive_index: f64) -> f64 {
@@ -75,27 +463,666 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, hit: &Hit) -> Option<(Color, Ray)> {
-        let attenuation = Color::new(1.0, 1.0, 1.0);
+    fn scatter(&self, r_in: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        let mut attenuation = Color::new(1.0, 1.0, 1.0);
+        let wavelength = r_in.wavelengths.map_or(D_LINE, |lambda| lambda.hero());
+        let ior = self.refraction_index.at(wavelength);
+        let refraction_index = if hit.front_face { 1.0 / ior } else { ior };
+
+        let unit_dir = r_in.dir.unit();
+
+        let scattered_dir = if let Some(film) = self.thin_film.filter(|_| hit.front_face) {
+            // the film reflects each channel differently; pick a branch by the
+            // average and reweight the channels.
+            let cos_theta = 1.0f64.min(dot(-unit_dir, hit.normal));
+            let lambda = r_in.channel_wavelengths();
+            let reflectance = Color::new(
+                film.reflectance(cos_theta, lambda[0], self.substrate(lambda[0])),
+                film.reflectance(cos_theta, lambda[1], self.substrate(lambda[1])),
+                film.reflectance(cos_theta, lambda[2], self.substrate(lambda[2])),
+            );
+            let p_reflect = (reflectance.x() + reflectance.y() + reflectance.z()) / 3.0;
+            if p_reflect > sampler.get_1d() {
+                attenuation *= reflectance / p_reflect;
+                reflect(unit_dir, hit.normal)
+            } else {
+                attenuation *= (Color::new(1.0, 1.0, 1.0) - reflectance) / (1.0 - p_reflect);
+                refract(unit_dir, hit.normal, refraction_index)
+            }
+        } else {
+            fresnel_scatter(unit_dir, hit.normal, refraction_index, sampler)
+        };
+        let scattered_ray = Ray::new(hit.p, scattered_dir);
+
+        Some((attenuation, scattered_ray))
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.refraction_index.is_dispersive()
+    }
+
+    fn absorption(&self) -> Option<Color> {
+        Some(self.absorption)
+    }
+}
+
+/// Perturbs the shading normal with a tangent-space normal map (RGB in [0, 1]
+/// encoding XYZ in [-1, 1], Z along the surface normal) before scattering with
+/// the wrapped material.
+#[derive(Debug)]
+pub struct NormalMapped {
+    base: Rc<dyn Material>,
+    map: Rc<dyn Texture>,
+}
+
+impl NormalMapped {
+    pub fn new(base: Rc<dyn Material>, map: Rc<dyn Texture>) -> Self {
+        Self { base, map }
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        // build the frame on the outside of the surface so maps don't mirror on
+        // back faces.
+        let n = hit.outward_normal();
+        let t = hit.tangent();
+        let b = cross(n, t);
+
+        let m = 2.0 * self.map.value(hit.u, hit.v, hit.p) - Vec3::new(1.0, 1.0, 1.0);
+        let mapped = (m.x() * t + m.y() * b + m.z() * n).unit();
+        scatter_shaded(self.base.as_ref(), r_in, hit, mapped, sampler)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+
+    fn absorption(&self) -> Option<Color> {
+        self.base.absorption()
+    }
+
+    fn opacity(&self, hit: &Hit) -> f64 {
+        self.base.opacity(hit)
+    }
+}
+
+/// Perturbs the shading normal as if the surface were displaced along its
+/// normal by `scale` times a scalar height texture.
+#[derive(Debug)]
+pub struct BumpMapped {
+    base: Rc<dyn Material>,
+    height: Rc<dyn Texture>,
+    scale: f64,
+}
+
+impl BumpMapped {
+    pub fn new(base: Rc<dyn Material>, height: Rc<dyn Texture>, scale: f64) -> Self {
+        Self {
+            base,
+            height,
+            scale,
+        }
+    }
+}
+
+impl Material for BumpMapped {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        const DELTA: f64 = 0.0005;
+
+        let n = hit.outward_normal();
+        let h = self.scale * self.height.scalar(hit.u, hit.v, hit.p);
+        let h_u = self.scale
+            * self
+                .height
+                .scalar(hit.u + DELTA, hit.v, hit.p + DELTA * hit.dpdu);
+        let h_v = self.scale
+            * self
+                .height
+                .scalar(hit.u, hit.v + DELTA, hit.p + DELTA * hit.dpdv);
+
+        let dpdu = hit.dpdu + ((h_u - h) / DELTA) * n;
+        let dpdv = hit.dpdv + ((h_v - h) / DELTA) * n;
+        let bumped = cross(dpdu, dpdv);
+        if bumped.norm_sq() < 1e-12 {
+            return self.base.scatter(r_in, hit, sampler);
+        }
+
+        let bumped = bumped.unit();
+        let bumped = if dot(bumped, n) < 0.0 {
+            -bumped
+        } else {
+            bumped
+        };
+        scatter_shaded(self.base.as_ref(), r_in, hit, bumped, sampler)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+
+    fn absorption(&self) -> Option<Color> {
+        self.base.absorption()
+    }
+
+    fn opacity(&self, hit: &Hit) -> f64 {
+        self.base.opacity(hit)
+    }
+}
+
+/// Cuts holes in the wrapped material where the mask texture's alpha is low
+/// (see `ImageTexture::load_mask`), for leaves, fences and decals. Masked-out
+/// hits are skipped during intersection, so rays continue as if the surface
+/// weren't there.
+#[derive(Debug)]
+pub struct AlphaMasked {
+    base: Rc<dyn Material>,
+    mask: Rc<dyn Texture>,
+}
+
+impl AlphaMasked {
+    pub fn new(base: Rc<dyn Material>, mask: Rc<dyn Texture>) -> Self {
+        Self { base, mask }
+    }
+}
+
+impl Material for AlphaMasked {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        self.base.scatter(r_in, hit, sampler)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+
+    fn absorption(&self) -> Option<Color> {
+        self.base.absorption()
+    }
+
+    fn opacity(&self, hit: &Hit) -> f64 {
+        self.mask.alpha(hit.u, hit.v, hit.p) * self.base.opacity(hit)
+    }
+}
+
+/// Translucent material for skin, wax and marble. Light refracts into the
+/// object, random-walks through its interior and leaves elsewhere on the
+/// boundary; the walk itself is traced by the camera, so the object must be
+/// closed. `mean_free_path` is the average distance between scattering events
+/// per channel, and must be positive; `albedo` is the chance of surviving each
+/// of them.
+#[derive(Debug)]
+pub struct Subsurface {
+    albedo: Color,
+    mean_free_path: Color,
+    refraction_index: f64,
+}
+
+impl Subsurface {
+    pub fn new(albedo: Color, mean_free_path: Color, refraction_index: f64) -> Self {
+        assert!(
+            (0..3).all(|i| mean_free_path[i] > 0.0),
+            "mean free path must be positive, got {:?}",
+            mean_free_path
+        );
+        Self {
+            albedo,
+            mean_free_path,
+            refraction_index,
+        }
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
         let refraction_index = if hit.front_face {
             1.0 / self.refraction_index
         } else {
             self.refraction_index
         };
 
-        let unit_dir = r_in.dir.unit();
-        let cos_theta = 1.0f64.min(dot(-unit_dir, hit.normal));
-        let sin_theta = (1.0 - cos_theta.powf(2.0)).sqrt();
+        let scattered_dir = fresnel_scatter(r_in.dir.unit(), hit.normal, refraction_index, sampler);
+        Some((Color::new(1.0, 1.0, 1.0), Ray::new(hit.p, scattered_dir)))
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        let mfp = self.mean_free_path;
+        Some(Medium {
+            sigma_t: Color::new(1.0 / mfp.x(), 1.0 / mfp.y(), 1.0 / mfp.z()),
+            albedo: self.albedo,
+        })
+    }
+}
+
+/// Randomly picks between two materials per hit; `weight` is the probability
+/// of choosing `b`. The pick is made with `Hit::choice`, so the hit's opacity
+/// and its scattering come from the same material.
+#[derive(Debug)]
+pub struct Mix {
+    a: Rc<dyn Material>,
+    b: Rc<dyn Material>,
+    weight: Rc<dyn Texture>,
+}
+
+impl Mix {
+    pub fn new(a: Rc<dyn Material>, b: Rc<dyn Material>, weight: f64) -> Self {
+        let weight = Rc::new(SolidColor::new(Color::new(weight, weight, weight)));
+        Self::textured(a, b, weight)
+    }
 
-        let scattered_dir = if sin_theta * refraction_index > 1.0
-            || Self::reflectance(cos_theta, refraction_index) > rand::rng().random::<f64>()
-        {
-            reflect(unit_dir, hit.normal)
+    pub fn textured(a: Rc<dyn Material>, b: Rc<dyn Material>, weight: Rc<dyn Texture>) -> Self {
+        Self { a, b, weight }
+    }
+
+    fn weight(&self, hit: &Hit) -> f64 {
+        self.weight.scalar(hit.u, hit.v, hit.p).clamp(0.0, 1.0)
+    }
+
+    // The chosen material, and the hit with its choice rescaled to [0, 1) so
+    // nested mixes get a fresh number.
+    fn pick(&self, hit: &Hit) -> (&dyn Material, Hit) {
+        let w = self.weight(hit);
+        let mut hit = hit.clone();
+        if hit.choice < w {
+            hit.choice /= w;
+            (self.b.as_ref(), hit)
         } else {
-            refract(unit_dir, hit.normal, refraction_index)
+            hit.choice = (hit.choice - w) / (1.0 - w);
+            (self.a.as_ref(), hit)
+        }
+    }
+}
+
+impl Material for Mix {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        let (material, hit) = self.pick(hit);
+        material.scatter(r_in, &hit, sampler)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.a.is_dispersive() || self.b.is_dispersive()
+    }
+
+    fn opacity(&self, hit: &Hit) -> f64 {
+        let (material, hit) = self.pick(hit);
+        material.opacity(&hit)
+    }
+}
+
+/// A smooth dielectric clear coat over another material: rays reflect off the
+/// coat with the Fresnel reflectance and otherwise scatter off the base, e.g.
+/// car paint or varnished wood.
+#[derive(Debug)]
+pub struct Coated {
+    base: Rc<dyn Material>,
+    refraction_index: f64,
+}
+
+impl Coated {
+    pub fn new(base: Rc<dyn Material>, refraction_index: f64) -> Self {
+        Self {
+            base,
+            refraction_index,
+        }
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, r_in: &Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        if hit.front_face {
+            let unit_dir = r_in.dir.unit();
+            let cos_theta = 1.0f64.min(dot(-unit_dir, hit.normal));
+            let reflectance = Dielectric::reflectance(cos_theta, 1.0 / self.refraction_index);
+            if reflectance > sampler.get_1d() {
+                let scattered_ray = Ray::new(hit.p, reflect(unit_dir, hit.normal));
+                return Some((Color::new(1.0, 1.0, 1.0), scattered_ray));
+            }
+        }
+        self.base.scatter(r_in, hit, sampler)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+
+    fn absorption(&self) -> Option<Color> {
+        self.base.absorption()
+    }
+
+    fn opacity(&self, hit: &Hit) -> f64 {
+        self.base.opacity(hit)
+    }
+}
+
+// Reflects or refracts at a smooth interface, picking in proportion to the
+// Fresnel reflectance; past the critical angle light always reflects.
+// `refraction_index` is the ratio of the indices on either side.
+fn fresnel_scatter(
+    unit_dir: Vec3,
+    normal: Vec3,
+    refraction_index: f64,
+    sampler: &mut dyn Sampler,
+) -> Vec3 {
+    let cos_theta = 1.0f64.min(dot(-unit_dir, normal));
+    let sin_theta = (1.0 - cos_theta.powf(2.0)).sqrt();
+    if sin_theta * refraction_index > 1.0
+        || Dielectric::reflectance(cos_theta, refraction_index) > sampler.get_1d()
+    {
+        reflect(unit_dir, normal)
+    } else {
+        refract(unit_dir, normal, refraction_index)
+    }
+}
+
+// Scatters `base` with the given outward-facing shading normal. Directions that
+// the shading normal and the true surface disagree about are absorbed rather
+// than leaking through the surface.
+fn scatter_shaded(
+    base: &dyn Material,
+    r_in: &Ray,
+    hit: &Hit,
+    outward_normal: Vec3,
+    sampler: &mut dyn Sampler,
+) -> Option<(Color, Ray)> {
+    let mut shading_hit = hit.clone();
+    shading_hit.normal = if hit.front_face {
+        outward_normal
+    } else {
+        -outward_normal
+    };
+
+    let (attenuation, scattered_ray) = base.scatter(r_in, &shading_hit, sampler)?;
+    let shading_side = dot(scattered_ray.dir, shading_hit.normal) > 0.0;
+    let geometric_side = dot(scattered_ray.dir, hit.geometric_normal) > 0.0;
+    if shading_side != geometric_side {
+        return None;
+    }
+    Some((attenuation, scattered_ray))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SamplerKind;
+    use crate::spectrum::SampledWavelengths;
+    use crate::vec3::Point;
+
+    // Scatters `r` off `material` at t = 1 on a surface facing +z, with u and v
+    // along x and y. `i` picks the sample.
+    fn scatter_at(
+        material: &Rc<dyn Material>,
+        r: &Ray,
+        i: u32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        let outward_normal = Vec3::new(0.0, 0.0, 1.0);
+        let hit = Hit::new(r, r.at(1.0), 1.0, &outward_normal, material.clone()).with_uv(
+            0.5,
+            0.5,
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        sampler.start_pixel_sample(i, 0, 0);
+        material.scatter(r, &hit, sampler)
+    }
+
+    #[test]
+    fn test_dispersive_indices() {
+        // Schott N-BK7, n_d = 1.5168.
+        let bk7 = RefractiveIndex::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
         };
-        let scattered_ray = Ray::new(hit.p, scattered_dir);
+        assert!((bk7.at(D_LINE) - 1.5168).abs() < 1e-4);
+        assert!(bk7.at(450.0) > bk7.at(650.0));
 
-        Some((attenuation, scattered_ray))
+        let cauchy = RefractiveIndex::Cauchy { a: 1.5, b: 0.004 };
+        assert!((cauchy.at(500.0) - (1.5 + 0.004 / 0.25)).abs() < 1e-12);
+
+        assert!(bk7.is_dispersive() && cauchy.is_dispersive());
+        assert!(!RefractiveIndex::Constant(1.5).is_dispersive());
+        assert!(!Dielectric::new(1.5).is_dispersive());
+        assert!(Dielectric::cauchy(1.5, 0.004).is_dispersive());
+    }
+
+    #[test]
+    fn test_refraction_follows_hero_wavelength() {
+        let glass: Rc<dyn Material> = Rc::new(Dielectric::cauchy(1.5, 0.02));
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        // the refracted ray's sine against the normal, or None if it reflected.
+        let mut refracted_sine = |hero_u: f64, i: u32| {
+            let mut r = Ray::new(Point::new(-1.0, 0.0, 1.0), Vec3::new(1.0, 0.0, -1.0));
+            r.wavelengths = Some(SampledWavelengths::sample_uniform(hero_u));
+            let (_, scattered_ray) = scatter_at(&glass, &r, i, sampler.as_mut()).unwrap();
+            let dir = scattered_ray.dir.unit();
+            (dir.z() < 0.0).then(|| dir.x())
+        };
+
+        let mut refracted = 0;
+        for i in 0..100 {
+            // 400nm and 680nm heroes.
+            if let (Some(blue), Some(red)) = (refracted_sine(0.05, i), refracted_sine(0.75, i)) {
+                assert!(blue < red - 0.005, "{} {}", blue, red);
+                refracted += 1;
+            }
+        }
+        assert!(refracted > 80, "{}", refracted);
+    }
+
+    #[test]
+    fn test_flat_maps_leave_scattering_unchanged() {
+        let base: Rc<dyn Material> = Rc::new(Lambertian::new(Color::new(0.8, 0.5, 0.2)));
+        let flat = Rc::new(SolidColor::new(Color::new(0.5, 0.5, 1.0)));
+        let level = Rc::new(SolidColor::new(Color::new(0.3, 0.3, 0.3)));
+        let normal_mapped: Rc<dyn Material> = Rc::new(NormalMapped::new(base.clone(), flat));
+        let bump_mapped: Rc<dyn Material> = Rc::new(BumpMapped::new(base.clone(), level, 2.0));
+
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        for i in 0..100 {
+            let dir = Vec3::new(0.01 * i as f64, 0.3, -1.0);
+            let r = Ray::new(Point::new(0.0, 0.0, 1.0), dir);
+            let (expected, expected_ray) = scatter_at(&base, &r, i, sampler.as_mut()).unwrap();
+            for material in [&normal_mapped, &bump_mapped] {
+                let (attenuation, scattered_ray) =
+                    scatter_at(material, &r, i, sampler.as_mut()).unwrap();
+                assert_eq!(attenuation, expected);
+                assert!((scattered_ray.dir - expected_ray.dir).norm() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_mix_opacity_matches_scatter() {
+        // half the hits land on a cut-out material, and those must never
+        // scatter off the opaque one.
+        let red: Rc<dyn Material> = Rc::new(Lambertian::new(Color::new(1.0, 0.0, 0.0)));
+        let clear = Rc::new(SolidColor::new(Color::default()).with_alpha(0.0));
+        let green = Rc::new(Lambertian::new(Color::new(0.0, 1.0, 0.0)));
+        let cut_out: Rc<dyn Material> = Rc::new(AlphaMasked::new(green, clear));
+        let mix: Rc<dyn Material> = Rc::new(Mix::new(red, cut_out, 0.5));
+
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        let outward_normal = Vec3::new(0.0, 0.0, 1.0);
+        let mut opaque = 0;
+        for i in 0..1000 {
+            let r = Ray::new(Point::new(i as f64, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+            let hit = Hit::new(&r, r.at(1.0), 1.0, &outward_normal, mix.clone());
+            if hit.is_cut_out(&r) {
+                continue;
+            }
+            opaque += 1;
+            sampler.start_pixel_sample(i, 0, 0);
+            let (attenuation, _) = mix.scatter(&r, &hit, sampler.as_mut()).unwrap();
+            assert_eq!(attenuation, Color::new(1.0, 0.0, 0.0));
+        }
+        assert!((400..600).contains(&opaque), "{}", opaque);
+    }
+
+    #[test]
+    fn test_mix_and_coated_weights() {
+        let n = 20000;
+        let red: Rc<dyn Material> = Rc::new(Lambertian::new(Color::new(1.0, 0.0, 0.0)));
+        let blue: Rc<dyn Material> = Rc::new(Lambertian::new(Color::new(0.0, 0.0, 1.0)));
+        let mix: Rc<dyn Material> = Rc::new(Mix::new(red, blue.clone(), 0.25));
+        // reflects off the coat with the normal-incidence reflectance, 0.04.
+        let coated: Rc<dyn Material> = Rc::new(Coated::new(blue, 1.5));
+
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        let mut share = |material: &Rc<dyn Material>, color: Color| {
+            let mut count = 0;
+            for i in 0..n {
+                let r = Ray::new(Point::new(i as f64, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+                let (attenuation, _) = scatter_at(material, &r, i, sampler.as_mut()).unwrap();
+                if attenuation == color {
+                    count += 1;
+                }
+            }
+            count as f64 / n as f64
+        };
+
+        assert!((share(&mix, Color::new(0.0, 0.0, 1.0)) - 0.25).abs() < 0.02);
+        assert!((share(&coated, Color::new(1.0, 1.0, 1.0)) - 0.04).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_diffuse_transmission_sides_and_weights() {
+        let reflectance = Color::new(0.6, 0.3, 0.1);
+        let transmittance = Color::new(0.1, 0.4, 0.5);
+        let sheet: Rc<dyn Material> = Rc::new(DiffuseTransmission::new(reflectance, transmittance));
+
+        // from either side, reflected light stays with `Hit::normal` and
+        // transmitted light leaves through the other hemisphere.
+        let n = 20000;
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        for dir_z in [-1.0, 1.0] {
+            let normal = Vec3::new(0.0, 0.0, -dir_z);
+            let mut reflected = Color::default();
+            let mut transmitted = Color::default();
+            for i in 0..n {
+                let r = Ray::new(Point::new(0.0, 0.0, -dir_z), Vec3::new(0.3, 0.0, dir_z));
+                let (weight, scattered_ray) = scatter_at(&sheet, &r, i, sampler.as_mut()).unwrap();
+                if dot(weight, reflectance - transmittance) > 0.0 {
+                    assert!(dot(scattered_ray.dir, normal) > 0.0);
+                    reflected += weight;
+                } else {
+                    assert!(dot(scattered_ray.dir, normal) < 0.0);
+                    transmitted += weight;
+                }
+            }
+            assert!((reflected / n as f64 - reflectance).norm() < 0.02);
+            assert!((transmitted / n as f64 - transmittance).norm() < 0.02);
+        }
+    }
+
+    #[test]
+    fn test_smooth_oren_nayar_is_lambertian() {
+        let albedo = Color::new(0.8, 0.5, 0.2);
+        let lambertian: Rc<dyn Material> = Rc::new(Lambertian::new(albedo));
+        let oren_nayar: Rc<dyn Material> = Rc::new(OrenNayar::new(albedo, 0.0));
+
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        for i in 0..100 {
+            let dir = Vec3::new(0.01 * i as f64, 0.3, -1.0);
+            let r = Ray::new(Point::new(0.0, 0.0, 1.0), dir);
+            let (expected, expected_ray) =
+                scatter_at(&lambertian, &r, i, sampler.as_mut()).unwrap();
+            let (attenuation, scattered_ray) =
+                scatter_at(&oren_nayar, &r, i, sampler.as_mut()).unwrap();
+            assert_eq!(attenuation, expected);
+            assert_eq!(scattered_ray.dir, expected_ray.dir);
+        }
+    }
+
+    #[test]
+    fn test_subsurface_boundary() {
+        // the boundary refracts exactly like glass of the same index.
+        let skin: Rc<dyn Material> = Rc::new(Subsurface::new(
+            Color::new(0.9, 0.6, 0.5),
+            Color::new(0.5, 0.25, 0.1),
+            1.4,
+        ));
+        let glass: Rc<dyn Material> = Rc::new(Dielectric::new(1.4));
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        for i in 0..100 {
+            let r = Ray::new(
+                Point::new(0.0, 0.0, 1.0),
+                Vec3::new(0.02 * i as f64, 0.0, -1.0),
+            );
+            let (attenuation, scattered_ray) = scatter_at(&skin, &r, i, sampler.as_mut()).unwrap();
+            let (_, expected_ray) = scatter_at(&glass, &r, i, sampler.as_mut()).unwrap();
+            assert_eq!(attenuation, Color::new(1.0, 1.0, 1.0));
+            assert_eq!(scattered_ray.dir, expected_ray.dir);
+        }
+
+        let medium = skin.medium().unwrap();
+        assert_eq!(medium.sigma_t, Color::new(2.0, 4.0, 10.0));
+        assert_eq!(medium.albedo, Color::new(0.9, 0.6, 0.5));
+    }
+
+    #[test]
+    #[should_panic(expected = "mean free path must be positive")]
+    fn test_subsurface_rejects_zero_mean_free_path() {
+        Subsurface::new(Color::new(0.9, 0.9, 0.9), Color::new(1.0, 0.0, 1.0), 1.4);
+    }
+
+    #[test]
+    fn test_vndf_weight_is_g2_over_g1() {
+        // a white isotropic conductor reflects everything, so the weight is
+        // the masking-shadowing ratio alone.
+        let alpha: f64 = 0.3;
+        let metal: Rc<dyn Material> = Rc::new(AnisotropicMetal::new(
+            Color::new(1.0, 1.0, 1.0),
+            alpha,
+            alpha,
+            0.0,
+        ));
+        let lambda = |w: Vec3| {
+            let tan_sq = (1.0 - w.z().powi(2)) / w.z().powi(2);
+            (-1.0 + (1.0 + alpha.powi(2) * tan_sq).sqrt()) / 2.0
+        };
+
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        let outward_normal = Vec3::new(0.0, 0.0, 1.0);
+        let dir = Vec3::new(0.6, 0.2, -0.5);
+        let wo = -dir.unit();
+        let r = Ray::new(Point::new(0.0, 0.0, 0.0) - dir, dir);
+        let hit = Hit::new(
+            &r,
+            Point::new(0.0, 0.0, 0.0),
+            1.0,
+            &outward_normal,
+            metal.clone(),
+        )
+        .with_uv(0.0, 0.0, Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        for i in 0..100 {
+            sampler.start_pixel_sample(i, 0, 0);
+            let Some((weight, scattered_ray)) = metal.scatter(&r, &hit, sampler.as_mut()) else {
+                continue;
+            };
+            let wi = scattered_ray.dir.unit();
+            let expected = (1.0 + lambda(wo)) / (1.0 + lambda(wo) + lambda(wi));
+            assert!((weight.x() - expected).abs() < 1e-9);
+            assert!(weight.x() <= 1.0 && weight.x() > 0.0);
+            assert_eq!(weight.x(), weight.z());
+        }
+    }
+
+    #[test]
+    fn test_thin_film_vanishes() {
+        // a film of zero thickness leaves the bare interface's reflectance.
+        let film = ThinFilm::new(0.0, 1.33);
+        let bare = ((1.0 - 1.5) / (1.0 + 1.5f64)).powf(2.0);
+        let r = film.reflectance(1.0, 550.0, Substrate::Dielectric(1.5));
+
+        assert!((r - bare).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_thin_film_quarter_wave() {
+        // a quarter-wave film of index sqrt(1.5) is a perfect anti-reflection coat.
+        let n = 1.5f64.sqrt();
+        let film = ThinFilm::new(550.0 / (4.0 * n), n);
+        let r = film.reflectance(1.0, 550.0, Substrate::Dielectric(1.5));
+
+        assert!(r.abs() < 1e-9);
     }
 }
@@ -1,22 +1,73 @@
+use crate::color::Color;
+use crate::spectrum::RGB_WAVELENGTHS;
+use crate::spectrum::SampledWavelengths;
 use crate::vec3::Vec3;
 use crate::vec3::Point;
 
 pub struct Ray {
     pub origin: Point,
     pub dir: Vec3,
+    // Set in spectral mode; colors along the path are then per-wavelength samples.
+    pub wavelengths: Option<SampledWavelengths>,
+    // Seconds into the animation.
+    pub time: f64,
+    // Beer-Lambert absorption per unit distance of the medium the ray travels
+    // through; zero in air.
+    pub absorption: Color,
 }
 
 impl Ray {
     pub fn new(origin: Point, dir: Vec3) -> Self {
         Self {
             origin,
-            dir
+            dir,
+            wavelengths: None,
+            time: 0.0,
+            absorption: Color::new(0.0, 0.0, 0.0),
         }
     }
 
+    /// Carries the path's wavelengths, time and medium over from the ray this
+    /// one was spawned from.
+    pub fn inherit(mut self, parent: &Ray) -> Self {
+        self.wavelengths = parent.wavelengths;
+        self.time = parent.time;
+        self.absorption = parent.absorption;
+        self
+    }
+
     pub fn at(&self, t: f64) -> Point {
         self.origin + self.dir * t
     }
+
+    /// Evaluates an RGB color at the ray's sampled wavelengths. In RGB mode the
+    /// color is returned unchanged.
+    pub fn spectral(&self, color: Color) -> Color {
+        match &self.wavelengths {
+            Some(lambda) => lambda.upsample(color),
+            None => color,
+        }
+    }
+
+    /// Fraction of the light that survives the ray's medium between its origin
+    /// and `at(t)`.
+    pub fn transmittance(&self, t: f64) -> Color {
+        let absorption = self.spectral(self.absorption);
+        let distance = t * self.dir.norm();
+        Color::new(
+            (-absorption.x() * distance).exp(),
+            (-absorption.y() * distance).exp(),
+            (-absorption.z() * distance).exp(),
+        )
+    }
+
+    /// The wavelength, in nanometers, that each color channel represents.
+    pub fn channel_wavelengths(&self) -> [f64; 3] {
+        match &self.wavelengths {
+            Some(lambda) => lambda.lambda(),
+            None => RGB_WAVELENGTHS,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -27,11 +78,26 @@ mod tests {
     fn test_at() {
         let ray = Ray {
             origin: Vec3::new(1.0, 0.0, 0.0),
-            dir: Vec3::new(0.0, 1.0, 0.0)
+            dir: Vec3::new(0.0, 1.0, 0.0),
+            wavelengths: None,
+            time: 0.0,
+            absorption: Color::new(0.0, 0.0, 0.0),
         };
 
         let expected = Vec3::new(1.0, 5.0, 0.0);
 
         assert_eq!(ray.at(5.0), expected);
     }
+
+    #[test]
+    fn test_transmittance() {
+        let mut ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0));
+        assert_eq!(ray.transmittance(10.0), Color::new(1.0, 1.0, 1.0));
+
+        ray.absorption = Color::new(0.0, 0.5, 1.0);
+        let transmittance = ray.transmittance(1.5);
+        for i in 0..3 {
+            assert!((transmittance[i] - (-3.0 * ray.absorption[i]).exp()).abs() < 1e-12);
+        }
+    }
 }
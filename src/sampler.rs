@@ -0,0 +1,370 @@
+// Largest f64 below one, so scaled samples never reach the upper bound.
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+// Bases for the Halton dimensions; further dimensions fall back to hashing.
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// A source of sample values in [0, 1). Each sample of a pixel draws its
+/// dimensions in the same order (wavelength, lens, then the path), so that
+/// stratified and low-discrepancy sequences line up across samples. Values
+/// depend only on the seed, pixel, sample index and dimension, so renders are
+/// reproducible whatever order the pixels are visited in.
+pub trait Sampler {
+    /// Starts sample `index` of pixel (i, j), rewinding to the first dimension.
+    fn start_pixel_sample(&mut self, i: u32, j: u32, index: u32);
+
+    fn get_1d(&mut self) -> f64;
+
+    fn get_2d(&mut self) -> [f64; 2];
+}
+
+/// Which sampler the camera draws its samples from.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum SamplerKind {
+    // Uniform random numbers; white noise.
+    #[default]
+    Independent,
+    // Each dimension split into one stratum per sample, randomly permuted.
+    Stratified {
+        jitter: bool,
+    },
+    // Halton sequence with per-pixel Owen scrambling.
+    Halton,
+    // Owen-scrambled Sobol points, padded across dimension pairs.
+    Sobol,
+}
+
+impl SamplerKind {
+    pub fn build(&self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+        let state = PixelState {
+            seed,
+            ..Default::default()
+        };
+        match *self {
+            Self::Independent => Box::new(IndependentSampler { state }),
+            Self::Stratified { jitter } => Box::new(StratifiedSampler {
+                samples_per_pixel,
+                jitter,
+                state,
+            }),
+            Self::Halton => Box::new(HaltonSampler { state }),
+            Self::Sobol => Box::new(SobolSampler {
+                samples_per_pixel,
+                state,
+            }),
+        }
+    }
+}
+
+pub struct IndependentSampler {
+    state: PixelState,
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, i: u32, j: u32, index: u32) {
+        self.state.start(i, j, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.state.random()
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        [self.state.random(), self.state.random()]
+    }
+}
+
+// The pixel sample being drawn and the next dimension to hand out.
+#[derive(Debug, Default, Copy, Clone)]
+struct PixelState {
+    seed: u64,
+    pixel: (u32, u32),
+    index: u32,
+    dimension: u32,
+}
+
+impl PixelState {
+    fn start(&mut self, i: u32, j: u32, index: u32) {
+        self.pixel = (i, j);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    // Hash of the pixel and the next `count` dimensions, consuming them.
+    fn next(&mut self, count: u32) -> u64 {
+        let hash = hash(&[
+            self.seed,
+            self.pixel.0 as u64,
+            self.pixel.1 as u64,
+            self.dimension as u64,
+        ]);
+        self.dimension += count;
+        hash
+    }
+
+    // Position of this sample in a random ordering of `n` strata. Samples past
+    // the first `n` (e.g. extra adaptive samples) start a fresh ordering.
+    fn stratum(&self, n: u32, hash: u64) -> u32 {
+        let round = (self.index / n) as u64;
+        permutation_element(self.index % n, n, hash ^ mix_bits(round))
+    }
+
+    // Uniform random value for the next dimension of this sample.
+    fn random(&mut self) -> f64 {
+        let hash = self.next(1);
+        to_unit(mix_bits(hash ^ mix_bits(self.index as u64 + 1)))
+    }
+}
+
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    jitter: bool,
+    state: PixelState,
+}
+
+impl StratifiedSampler {
+    fn offset(&self, hash: u64) -> f64 {
+        if self.jitter {
+            to_unit(mix_bits(hash ^ (self.state.index as u64 + 1)))
+        } else {
+            0.5
+        }
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, i: u32, j: u32, index: u32) {
+        self.state.start(i, j, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let hash = self.state.next(1);
+        let n = self.samples_per_pixel.max(1);
+        let stratum = self.state.stratum(n, hash);
+        (stratum as f64 + self.offset(hash)) / n as f64
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        let hash = self.state.next(2);
+        // the smallest square grid with a stratum for every sample.
+        let side = (self.samples_per_pixel.max(1) as f64).sqrt().ceil() as u32;
+        let n = side * side;
+        let stratum = self.state.stratum(n, hash);
+        [
+            ((stratum % side) as f64 + self.offset(hash)) / side as f64,
+            ((stratum / side) as f64 + self.offset(mix_bits(hash))) / side as f64,
+        ]
+    }
+}
+
+pub struct HaltonSampler {
+    state: PixelState,
+}
+
+impl HaltonSampler {
+    fn sample(&mut self) -> f64 {
+        let dimension = self.state.dimension as usize;
+        let hash = self.state.next(1);
+        match PRIMES.get(dimension) {
+            Some(&base) => owen_scrambled_radical_inverse(base, self.state.index as u64, hash),
+            None => to_unit(mix_bits(hash ^ self.state.index as u64)),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, i: u32, j: u32, index: u32) {
+        self.state.start(i, j, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.sample()
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        [self.sample(), self.sample()]
+    }
+}
+
+/// Padded Sobol: every dimension (or pair of dimensions) uses the first one
+/// or two Sobol dimensions with its own shuffle of the sample order and its
+/// own Owen scramble, which keeps the points well stratified at any depth.
+pub struct SobolSampler {
+    samples_per_pixel: u32,
+    state: PixelState,
+}
+
+impl SobolSampler {
+    // Each run of `samples_per_pixel` samples takes the next block of points.
+    fn shuffled_index(&self, hash: u64) -> u32 {
+        let n = self.samples_per_pixel.max(1);
+        self.state.stratum(n, hash) + self.state.index / n * n
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, i: u32, j: u32, index: u32) {
+        self.state.start(i, j, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let hash = self.state.next(1);
+        let index = self.shuffled_index(hash);
+        sobol(index, 0, hash as u32)
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        let hash = self.state.next(2);
+        let index = self.shuffled_index(hash);
+        [
+            sobol(index, 0, hash as u32),
+            sobol(index, 1, (hash >> 32) as u32),
+        ]
+    }
+}
+
+// Point `index` of one of the first two Sobol dimensions, Owen scrambled.
+fn sobol(index: u32, dimension: u32, seed: u32) -> f64 {
+    let mut v = if dimension == 0 {
+        index.reverse_bits()
+    } else {
+        // the second dimension's generator matrix is Pascal's triangle mod 2.
+        let mut x = 0u32;
+        let mut direction = 1u32 << 31;
+        let mut bits = index;
+        while bits != 0 {
+            if bits & 1 != 0 {
+                x ^= direction;
+            }
+            direction ^= direction >> 1;
+            bits >>= 1;
+        }
+        x
+    };
+    v = owen_scramble(v, seed);
+    (v as f64 / 4294967296.0).min(ONE_MINUS_EPSILON)
+}
+
+// Fast hash-based approximation of Owen scrambling (Burley 2020), applied to
+// the bits of a fixed-point sample.
+fn owen_scramble(v: u32, seed: u32) -> u32 {
+    let mut v = v.reverse_bits();
+    v ^= v.wrapping_mul(0x3d20adea);
+    v = v.wrapping_add(seed);
+    v = v.wrapping_mul((seed >> 16) | 1);
+    v ^= v.wrapping_mul(0x05526c56);
+    v ^= v.wrapping_mul(0x53a22864);
+    v.reverse_bits()
+}
+
+// Radical inverse of `a` in `base`, with each digit permuted depending on the
+// digits before it.
+fn owen_scrambled_radical_inverse(base: u64, mut a: u64, hash: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_m = 1.0;
+    let mut reversed_digits = 0u64;
+    while 1.0 - (base - 1) as f64 * inv_base_m < 1.0 {
+        let next = a / base;
+        let digit_hash = mix_bits(hash ^ reversed_digits);
+        let digit = permutation_element((a - next * base) as u32, base as u32, digit_hash);
+        reversed_digits = reversed_digits * base + digit as u64;
+        inv_base_m *= inv_base;
+        a = next;
+    }
+    (reversed_digits as f64 * inv_base_m).min(ONE_MINUS_EPSILON)
+}
+
+// Element `i` of a random permutation of 0..n chosen by `seed`, without
+// building the permutation (Kensler 2013).
+fn permutation_element(mut i: u32, n: u32, seed: u64) -> u32 {
+    let seed = seed as u32;
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            break;
+        }
+    }
+    (i.wrapping_add(seed)) % n
+}
+
+fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e3779b97f4a7c15, |h, &v| {
+        mix_bits(h ^ v.wrapping_add(h << 6))
+    })
+}
+
+// Uniform value in [0, 1) from the top 53 bits.
+fn to_unit(bits: u64) -> f64 {
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permutation_element() {
+        for n in [1, 7, 16, 100] {
+            let mut seen: Vec<u32> = (0..n).map(|i| permutation_element(i, n, 12345)).collect();
+            seen.sort();
+            assert_eq!(seen, (0..n).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_samplers_stratify() {
+        // every sampler but the independent one puts exactly one of n samples
+        // in each of n equal intervals of the first dimension.
+        let n = 16;
+        for kind in [
+            SamplerKind::Stratified { jitter: true },
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let mut sampler = kind.build(n, 0);
+            let mut counts = vec![0; n as usize];
+            for index in 0..n {
+                sampler.start_pixel_sample(3, 5, index);
+                let u = sampler.get_1d();
+                assert!((0.0..1.0).contains(&u));
+                counts[(u * n as f64) as usize] += 1;
+            }
+            assert!(counts.iter().all(|&c| c == 1), "{:?}: {:?}", kind, counts);
+        }
+    }
+}
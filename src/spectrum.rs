@@ -0,0 +1,210 @@
+use crate::color::Color;
+use crate::vec3::Vec3;
+
+use std::sync::LazyLock;
+
+// Visible range covered by the wavelength samples, in nanometers.
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 780.0;
+
+// Fraunhofer d-line; dispersive media use their index here in RGB mode.
+pub const D_LINE: f64 = 587.56;
+
+// Wavelengths standing in for the R, G and B channels outside spectral mode.
+pub const RGB_WAVELENGTHS: [f64; 3] = [630.0, 532.0, 465.0];
+
+const SMITS_MIN: f64 = 380.0;
+const SMITS_BIN_WIDTH: f64 = 34.0;
+
+// Smits' RGB-to-spectrum basis, ten bins over 380..720nm.
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+// RGB of a constant unit spectrum, used to white balance the XYZ -> RGB step so
+// that grey albedos and the sky keep the same color they have in RGB mode.
+static WHITE_RGB: LazyLock<Color> = LazyLock::new(|| {
+    let steps = (LAMBDA_MAX - LAMBDA_MIN) as u32;
+    let mut xyz = Vec3::new(0.0, 0.0, 0.0);
+    for i in 0..steps {
+        xyz += cie_xyz(LAMBDA_MIN + i as f64 + 0.5);
+    }
+    xyz_to_linear_srgb(xyz)
+});
+
+/// A hero wavelength plus two companions spread evenly over the visible range.
+/// Spectral radiance and reflectance at these wavelengths are carried in the
+/// three components of a `Color`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SampledWavelengths {
+    lambda: [f64; 3],
+    secondary_terminated: bool,
+}
+
+impl SampledWavelengths {
+    pub fn sample_uniform(u: f64) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let mut lambda = [0.0; 3];
+        for (i, l) in lambda.iter_mut().enumerate() {
+            let offset = (u + i as f64 / 3.0).fract();
+            *l = LAMBDA_MIN + offset * range;
+        }
+
+        Self {
+            lambda,
+            secondary_terminated: false,
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    pub fn lambda(&self) -> [f64; 3] {
+        self.lambda
+    }
+
+    /// Evaluates an RGB reflectance or emission at each sampled wavelength.
+    pub fn upsample(&self, rgb: Color) -> Color {
+        Vec3::new(
+            rgb_to_spectrum(rgb, self.lambda[0]),
+            rgb_to_spectrum(rgb, self.lambda[1]),
+            rgb_to_spectrum(rgb, self.lambda[2]),
+        )
+    }
+
+    /// Drops the companion wavelengths once the path direction depends on the
+    /// hero wavelength (e.g. refraction through a dispersive medium). Returns
+    /// the weight to fold into the path throughput.
+    pub fn terminate_secondary(&mut self) -> Color {
+        if self.secondary_terminated {
+            return Vec3::new(1.0, 1.0, 1.0);
+        }
+        self.secondary_terminated = true;
+        Vec3::new(3.0, 0.0, 0.0)
+    }
+
+    /// Converts spectral radiance at the sampled wavelengths to linear RGB.
+    pub fn to_rgb(&self, radiance: Color) -> Color {
+        let mut xyz = Vec3::new(0.0, 0.0, 0.0);
+        for i in 0..3 {
+            xyz += radiance[i] * cie_xyz(self.lambda[i]);
+        }
+        // uniform pdf over the range, averaged over three wavelengths.
+        xyz *= (LAMBDA_MAX - LAMBDA_MIN) / 3.0;
+
+        let white = *WHITE_RGB;
+        let rgb = xyz_to_linear_srgb(xyz);
+        Vec3::new(
+            rgb.x() / white.x(),
+            rgb.y() / white.y(),
+            rgb.z() / white.z(),
+        )
+    }
+}
+
+/// CIE 1931 2-degree color matching functions, using the multi-lobe Gaussian
+/// fit from Wyman, Sloan and Shirley (2013).
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    let x = 1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * lobe(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8);
+    Vec3::new(x, y, z)
+}
+
+pub fn xyz_to_linear_srgb(xyz: Vec3) -> Color {
+    Vec3::new(
+        3.2404542 * xyz.x() - 1.5371385 * xyz.y() - 0.4985314 * xyz.z(),
+        -0.9692660 * xyz.x() + 1.8760108 * xyz.y() + 0.0415560 * xyz.z(),
+        0.0556434 * xyz.x() - 0.2040259 * xyz.y() + 1.0572252 * xyz.z(),
+    )
+}
+
+/// Smits' (1999) RGB to spectrum conversion, evaluated at a single wavelength.
+pub fn rgb_to_spectrum(rgb: Color, lambda: f64) -> f64 {
+    let bin = ((lambda - SMITS_MIN) / SMITS_BIN_WIDTH).clamp(0.0, 9.0) as usize;
+    let (r, g, b) = (rgb.x(), rgb.y(), rgb.z());
+
+    if r <= g && r <= b {
+        let base = r * SMITS_WHITE[bin];
+        if g <= b {
+            base + (g - r) * SMITS_CYAN[bin] + (b - g) * SMITS_BLUE[bin]
+        } else {
+            base + (b - r) * SMITS_CYAN[bin] + (g - b) * SMITS_GREEN[bin]
+        }
+    } else if g <= r && g <= b {
+        let base = g * SMITS_WHITE[bin];
+        if r <= b {
+            base + (r - g) * SMITS_MAGENTA[bin] + (b - r) * SMITS_BLUE[bin]
+        } else {
+            base + (b - g) * SMITS_MAGENTA[bin] + (r - b) * SMITS_RED[bin]
+        }
+    } else {
+        let base = b * SMITS_WHITE[bin];
+        if r <= g {
+            base + (r - b) * SMITS_YELLOW[bin] + (g - r) * SMITS_GREEN[bin]
+        } else {
+            base + (g - b) * SMITS_YELLOW[bin] + (r - g) * SMITS_RED[bin]
+        }
+    }
+}
+
+fn lobe(x: f64, mu: f64, sigma_lo: f64, sigma_hi: f64) -> f64 {
+    let sigma = if x < mu { sigma_lo } else { sigma_hi };
+    (-0.5 * ((x - mu) / sigma).powf(2.0)).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upsample_white() {
+        let lambda = SampledWavelengths::sample_uniform(0.3);
+        let s = lambda.upsample(Vec3::new(1.0, 1.0, 1.0));
+        for i in 0..3 {
+            assert!((s[i] - 1.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_white_round_trip() {
+        let n = 4000;
+        let mut rgb = Vec3::new(0.0, 0.0, 0.0);
+        for i in 0..n {
+            let lambda = SampledWavelengths::sample_uniform((i as f64 + 0.5) / n as f64);
+            rgb += lambda.to_rgb(Vec3::new(1.0, 1.0, 1.0));
+        }
+        rgb /= n as f64;
+
+        for i in 0..3 {
+            assert!((rgb[i] - 1.0).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn test_terminate_secondary_once() {
+        let mut lambda = SampledWavelengths::sample_uniform(0.5);
+        assert_eq!(lambda.terminate_secondary(), Vec3::new(3.0, 0.0, 0.0));
+        assert_eq!(lambda.terminate_secondary(), Vec3::new(1.0, 1.0, 1.0));
+    }
+}
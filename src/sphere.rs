@@ -4,9 +4,12 @@ use crate::interval::Interval;
 use crate::material::Material;
 use crate::ray::Ray;
 use crate::vec3::Point;
+use crate::vec3::Vec3;
 use crate::vec3::dot;
+use std::f64::consts::PI;
 use std::rc::Rc;
 
+#[derive(Debug)]
 pub struct Sphere {
     center: Point,
     radius: f64,
@@ -21,6 +24,24 @@ impl Sphere {
             mat,
         }
     }
+
+    // Maps a point on the unit sphere to (u, v) in [0, 1] and returns the
+    // derivatives of the surface position along u and v.
+    fn surface_coords(&self, p: Vec3) -> (f64, f64, Vec3, Vec3) {
+        let theta = (-p.y()).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+
+        let dpdu = 2.0 * PI * self.radius * Vec3::new(p.z(), 0.0, -p.x());
+        let dpdv = PI
+            * self.radius
+            * Vec3::new(
+                -theta.cos() * phi.cos(),
+                theta.sin(),
+                theta.cos() * phi.sin(),
+            );
+
+        (phi / (2.0 * PI), theta / PI, dpdu, dpdv)
+    }
 }
 
 impl Hittable for Sphere {
@@ -36,18 +57,140 @@ impl Hittable for Sphere {
         }
 
         let sqrt_discriminant = discriminant.sqrt();
-        let mut root = (h - sqrt_discriminant) / a;
-        if !ray_t.surrounds(root) {
-            root = (h + sqrt_discriminant) / a;
+        for root in [(h - sqrt_discriminant) / a, (h + sqrt_discriminant) / a] {
             if !ray_t.surrounds(root) {
-                return None;
+                continue;
             }
+
+            let hit_point = ray.at(root);
+            let outward_normal = (hit_point - self.center).unit();
+            let (u, v, dpdu, dpdv) = self.surface_coords(outward_normal);
+            let hit = Hit::new(ray, hit_point, root, &outward_normal, self.mat.clone())
+                .with_uv(u, v, dpdu, dpdv);
+
+            if !hit.is_cut_out(ray) {
+                return Some(hit);
+            }
+        }
+
+        None
+    }
+
+    fn materials(&self) -> Vec<Rc<dyn Material>> {
+        vec![self.mat.clone()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable_list::HittableList;
+    use crate::material::AlphaMasked;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+    use crate::texture::Texture;
+
+    #[test]
+    fn test_surface_derivatives() {
+        let material = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = Sphere::new(Point::new(1.0, 2.0, 3.0), 2.0, material);
+        // the point at (u, v), inverting `surface_coords`.
+        let position = |u: f64, v: f64| {
+            let (theta, phi) = (v * PI, u * 2.0 * PI);
+            let normal = Vec3::new(
+                -theta.sin() * phi.cos(),
+                -theta.cos(),
+                theta.sin() * phi.sin(),
+            );
+            sphere.center + sphere.radius * normal
+        };
+
+        let h = 1e-6;
+        for (u, v) in [(0.1, 0.3), (0.4, 0.5), (0.7, 0.8), (0.95, 0.15)] {
+            let normal = (position(u, v) - sphere.center) / sphere.radius;
+            let (su, sv, dpdu, dpdv) = sphere.surface_coords(normal);
+            assert!((su - u).abs() < 1e-9 && (sv - v).abs() < 1e-9);
+
+            let du = (position(u + h, v) - position(u - h, v)) / (2.0 * h);
+            let dv = (position(u, v + h) - position(u, v - h)) / (2.0 * h);
+            assert!((dpdu - du).norm() < 1e-6 * du.norm(), "{:?} {:?}", dpdu, du);
+            assert!((dpdv - dv).norm() < 1e-6 * dv.norm(), "{:?} {:?}", dpdv, dv);
+            assert!(dot(dpdu, normal).abs() < 1e-9);
+            assert!(dot(dpdv, normal).abs() < 1e-9);
+        }
+    }
+
+    // Transparent in front of the plane z = -2, opaque behind it.
+    #[derive(Debug)]
+    struct FrontCutOut;
+
+    impl Texture for FrontCutOut {
+        fn value(&self, _u: f64, _v: f64, _p: Point) -> Color {
+            Color::new(1.0, 1.0, 1.0)
+        }
+
+        fn alpha(&self, _u: f64, _v: f64, p: Point) -> f64 {
+            if p.z() > -2.0 { 0.0 } else { 1.0 }
+        }
+    }
+
+    fn masked(mask: Rc<dyn Texture>) -> Rc<dyn Material> {
+        let base = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        Rc::new(AlphaMasked::new(base, mask))
+    }
+
+    #[test]
+    fn test_cut_out_hit_is_skipped() {
+        let clear = Rc::new(SolidColor::new(Color::default()).with_alpha(0.0));
+        let solid = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut world = HittableList::new();
+        world.add(Rc::new(Sphere::new(
+            Point::new(0.0, 0.0, -2.0),
+            0.5,
+            masked(clear),
+        )));
+        world.add(Rc::new(Sphere::new(Point::new(0.0, 0.0, -5.0), 0.5, solid)));
+
+        let r = Ray::new(Point::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = world.hit(&r, Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert_eq!(hit.t, 4.5);
+        assert_eq!(hit.object_id, 2);
+        assert_eq!(hit.material_id, 2);
+    }
+
+    #[test]
+    fn test_material_ids_follow_scene_order() {
+        let red: Rc<dyn Material> = Rc::new(Lambertian::new(Color::new(0.8, 0.1, 0.1)));
+        let blue: Rc<dyn Material> = Rc::new(Lambertian::new(Color::new(0.1, 0.1, 0.8)));
+        let mut world = HittableList::new();
+        for (z, mat) in [(-2.0, &red), (-4.0, &blue), (-6.0, &red)] {
+            world.add(Rc::new(Sphere::new(
+                Point::new(0.0, 0.0, z),
+                0.5,
+                mat.clone(),
+            )));
         }
 
-        let hit_point = ray.at(root);
-        let outward_normal = (hit_point - self.center).unit();
-        let hit = Hit::new(ray, hit_point, root, &outward_normal, self.mat.clone());
+        // hit from behind, so the last sphere is nearest.
+        let r = Ray::new(Point::new(0.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0));
+        let hit = world.hit(&r, Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert_eq!((hit.object_id, hit.material_id), (3, 1));
+        // past it, the middle one is made of the second material added.
+        let hit = world.hit(&r, Interval::new(5.0, f64::INFINITY)).unwrap();
+        assert_eq!((hit.object_id, hit.material_id), (2, 2));
+    }
 
-        Some(hit)
+    #[test]
+    fn test_cut_out_front_takes_far_root() {
+        let sphere = Sphere::new(
+            Point::new(0.0, 0.0, -2.0),
+            0.5,
+            masked(Rc::new(FrontCutOut)),
+        );
+        let r = Ray::new(Point::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = sphere.hit(&r, Interval::new(0.001, f64::INFINITY)).unwrap();
+        assert_eq!(hit.t, 2.5);
+        assert!(!hit.front_face);
     }
 }
@@ -0,0 +1,242 @@
+use crate::color::Color;
+use crate::tonemap::srgb_decode;
+use crate::vec3::Point;
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub trait Texture: fmt::Debug {
+    fn value(&self, u: f64, v: f64, p: Point) -> Color;
+
+    // Single-channel lookup for height maps and weights.
+    fn scalar(&self, u: f64, v: f64, p: Point) -> f64 {
+        let c = self.value(u, v, p);
+        (c.x() + c.y() + c.z()) / 3.0
+    }
+
+    // Coverage, for opacity masks; textures without an alpha channel are
+    // opaque.
+    fn alpha(&self, _u: f64, _v: f64, _p: Point) -> f64 {
+        1.0
+    }
+}
+
+#[derive(Debug)]
+pub struct SolidColor {
+    albedo: Color,
+    alpha: f64,
+}
+
+impl SolidColor {
+    pub fn new(albedo: Color) -> Self {
+        Self { albedo, alpha: 1.0 }
+    }
+
+    pub fn with_alpha(mut self, alpha: f64) -> Self {
+        self.alpha = alpha;
+        self
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: Point) -> Color {
+        self.albedo
+    }
+
+    fn alpha(&self, _u: f64, _v: f64, _p: Point) -> f64 {
+        self.alpha
+    }
+}
+
+/// A texture backed by a PPM (P3 or P6) or greyscale PGM (P2 or P5) image,
+/// sampled with nearest-neighbor lookup. `v = 0` is the bottom row of the
+/// image.
+#[derive(Debug)]
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    // Per-pixel alpha; empty for opaque images.
+    alpha: Vec<f64>,
+}
+
+impl ImageTexture {
+    /// Loads an sRGB-encoded color image, such as the renderer writes by
+    /// default, converting it back to linear values.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut image = Self::from_ppm(&fs::read(path)?)?;
+        for pixel in image.pixels.iter_mut() {
+            *pixel = pixel.map(srgb_decode);
+        }
+        Ok(image)
+    }
+
+    /// Loads an image as raw data (normal maps, height maps).
+    pub fn load_linear<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_ppm(&fs::read(path)?)
+    }
+
+    /// Loads an opacity mask: the first channel of the image, ideally a PGM,
+    /// becomes the texture's alpha.
+    pub fn load_mask<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::from_ppm(&fs::read(path)?)?.into_mask())
+    }
+
+    fn into_mask(mut self) -> Self {
+        self.alpha = self.pixels.iter().map(|c| c.x()).collect();
+        self
+    }
+
+    fn index(&self, u: f64, v: f64) -> usize {
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0);
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+        j * self.width + i
+    }
+
+    pub fn from_ppm(bytes: &[u8]) -> io::Result<Self> {
+        let mut pos = 0;
+        let magic = next_token(bytes, &mut pos)?;
+        let width = parse_token(bytes, &mut pos)?;
+        let height = parse_token(bytes, &mut pos)?;
+        let maxval = parse_token(bytes, &mut pos)?;
+        if maxval == 0 || maxval > 65535 {
+            return Err(invalid("bad PPM maxval"));
+        }
+
+        let channels = match magic {
+            b"P2" | b"P5" => 1,
+            _ => 3,
+        };
+        let count = width * height * channels;
+        let samples: Vec<usize> = match magic {
+            b"P2" | b"P3" => (0..count)
+                .map(|_| parse_token(bytes, &mut pos))
+                .collect::<io::Result<_>>()?,
+            b"P5" | b"P6" => {
+                // a single whitespace byte separates the header from the raster.
+                pos += 1;
+                let size = if maxval < 256 { 1 } else { 2 };
+                let raster = bytes
+                    .get(pos..pos + count * size)
+                    .ok_or_else(|| invalid("truncated PPM raster"))?;
+                raster
+                    .chunks(size)
+                    .map(|c| c.iter().fold(0, |acc, &b| (acc << 8) | b as usize))
+                    .collect()
+            }
+            _ => return Err(invalid("not a PPM or PGM image")),
+        };
+
+        // greyscale pixels repeat their one channel.
+        let scale = 1.0 / maxval as f64;
+        let pixels = samples
+            .chunks(channels)
+            .map(|c| {
+                Color::new(c[0] as f64, c[channels / 2] as f64, c[channels - 1] as f64) * scale
+            })
+            .collect();
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+            alpha: Vec::new(),
+        })
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: Point) -> Color {
+        if self.pixels.is_empty() {
+            return Color::new(0.0, 1.0, 1.0);
+        }
+        self.pixels[self.index(u, v)]
+    }
+
+    fn alpha(&self, u: f64, v: f64, _p: Point) -> f64 {
+        if self.alpha.is_empty() {
+            return 1.0;
+        }
+        self.alpha[self.index(u, v)]
+    }
+}
+
+fn next_token<'a>(bytes: &'a [u8], pos: &mut usize) -> io::Result<&'a [u8]> {
+    loop {
+        while *pos < bytes.len() && bytes[*pos].is_ascii_whitespace() {
+            *pos += 1;
+        }
+        if *pos < bytes.len() && bytes[*pos] == b'#' {
+            while *pos < bytes.len() && bytes[*pos] != b'\n' {
+                *pos += 1;
+            }
+        } else {
+            break;
+        }
+    }
+
+    let start = *pos;
+    while *pos < bytes.len() && !bytes[*pos].is_ascii_whitespace() {
+        *pos += 1;
+    }
+    if start == *pos {
+        return Err(invalid("unexpected end of PPM data"));
+    }
+    Ok(&bytes[start..*pos])
+}
+
+fn parse_token(bytes: &[u8], pos: &mut usize) -> io::Result<usize> {
+    let token = next_token(bytes, pos)?;
+    std::str::from_utf8(token)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| invalid("bad number in PPM data"))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_ppm() {
+        let ppm = b"P3\n# two pixels\n2 1\n255\n255 0 0  0 0 255\n";
+        let image = ImageTexture::from_ppm(ppm).unwrap();
+        let p = Point::new(0.0, 0.0, 0.0);
+
+        assert_eq!(image.value(0.25, 0.5, p), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(image.value(0.75, 0.5, p), Color::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_pgm_mask() {
+        let mut pgm = b"P5 2 1 255\n".to_vec();
+        pgm.extend_from_slice(&[0, 51]);
+        let mask = ImageTexture::from_ppm(&pgm).unwrap().into_mask();
+        let p = Point::new(0.0, 0.0, 0.0);
+
+        assert_eq!(mask.value(0.75, 0.5, p), Color::new(0.2, 0.2, 0.2));
+        assert_eq!(mask.alpha(0.25, 0.5, p), 0.0);
+        assert_eq!(mask.alpha(0.75, 0.5, p), 0.2);
+        assert_eq!(SolidColor::new(Color::default()).alpha(0.0, 0.0, p), 1.0);
+    }
+
+    #[test]
+    fn test_binary_ppm() {
+        let mut ppm = b"P6 1 1 255\n".to_vec();
+        ppm.extend_from_slice(&[0, 255, 0]);
+        let image = ImageTexture::from_ppm(&ppm).unwrap();
+
+        assert_eq!(
+            image.value(0.5, 0.5, Point::new(0.0, 0.0, 0.0)),
+            Color::new(0.0, 1.0, 0.0)
+        );
+    }
+}
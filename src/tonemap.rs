@@ -0,0 +1,289 @@
+use crate::color::Color;
+use crate::color::luminance;
+use crate::image::Image;
+use crate::spectrum::xyz_to_linear_srgb;
+use crate::vec3::Vec3;
+
+// Linear Rec. 709 to CIE XYZ.
+const RGB_TO_XYZ: [[f64; 3]; 3] = [
+    [0.4124564, 0.3575761, 0.1804375],
+    [0.2126729, 0.7151522, 0.0721750],
+    [0.0193339, 0.1191920, 0.9503041],
+];
+
+// Bradford cone response, for chromatic adaptation.
+const XYZ_TO_LMS: [[f64; 3]; 3] = [
+    [0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135, 0.0367],
+    [0.0389, -0.0685, 1.0296],
+];
+const LMS_TO_XYZ: [[f64; 3]; 3] = [
+    [0.9869929, -0.1470543, 0.1599627],
+    [0.4323053, 0.5183603, 0.0492912],
+    [-0.0085287, 0.0400428, 0.9684867],
+];
+
+// Linear Rec. 709 to the wider gamuts; all share the D65 white point.
+const REC709_TO_P3: [[f64; 3]; 3] = [
+    [0.8224621, 0.1775380, 0.0],
+    [0.0331941, 0.9668058, 0.0],
+    [0.0170827, 0.0723974, 0.9105199],
+];
+const REC709_TO_REC2020: [[f64; 3]; 3] = [
+    [0.6274040, 0.3292820, 0.0433136],
+    [0.0690970, 0.9195400, 0.0113612],
+    [0.0163916, 0.0880132, 0.8955950],
+];
+
+// Color temperature white balancing is relative to, about that of D65.
+const NEUTRAL_TEMPERATURE: f64 = 6504.0;
+
+// Scene value that stays put under the contrast control.
+const MIDDLE_GREY: f64 = 0.18;
+
+/// Curve compressing scene radiance into the displayable range.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum ToneMapping {
+    // Clip at 1.
+    #[default]
+    None,
+    // Extended Reinhard on luminance; `white` is the smallest value mapped to
+    // white.
+    Reinhard {
+        white: f64,
+    },
+    // John Hable's Uncharted 2 filmic curve.
+    Hable,
+    // Krzysztof Narkowicz's fit of the ACES reference and output transforms.
+    Aces,
+}
+
+/// Display the image is encoded for: the primaries its values refer to and
+/// the transfer curve applied to them.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum ColorSpace {
+    #[default]
+    Srgb,
+    // P3 primaries with the sRGB curve.
+    DisplayP3,
+    // Rec. 2020 primaries with the Rec. 709 / 2020 camera curve.
+    Rec2020,
+}
+
+/// Turns the linear render into display values in [0, 1]: exposure, white
+/// balance, then grading and tone mapping in linear Rec. 709, and finally
+/// conversion to the output color space.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ToneMap {
+    // Compensation in stops, on top of the camera's own exposure.
+    pub exposure: f64,
+    // Color temperature in kelvin of the light that should look white.
+    pub white_balance: Option<f64>,
+    // 0 is greyscale, 1 leaves colors alone.
+    pub saturation: f64,
+    // Power applied around middle grey; above 1 adds contrast.
+    pub contrast: f64,
+    pub tone_mapping: ToneMapping,
+    pub color_space: ColorSpace,
+}
+
+impl Default for ToneMap {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            white_balance: None,
+            saturation: 1.0,
+            contrast: 1.0,
+            tone_mapping: ToneMapping::None,
+            color_space: ColorSpace::Srgb,
+        }
+    }
+}
+
+impl ToneMap {
+    pub fn apply(&self, color: Color) -> Color {
+        let mut c = 2f64.powf(self.exposure) * color;
+        if let Some(temperature) = self.white_balance {
+            c = adapt(c, temperature);
+        }
+
+        let y = luminance(c);
+        let grey = Vec3::new(y, y, y);
+        c = (grey + self.saturation * (c - grey)).map(|x| x.max(0.0));
+        c = c.map(|x| MIDDLE_GREY * (x / MIDDLE_GREY).powf(self.contrast));
+
+        c = match self.tone_mapping {
+            ToneMapping::None => c,
+            ToneMapping::Reinhard { white } => {
+                let y = luminance(c);
+                if y > 0.0 {
+                    c * ((1.0 + y / (white * white)) / (1.0 + y))
+                } else {
+                    c
+                }
+            }
+            ToneMapping::Hable => {
+                // exposure bias and linear white point of the original.
+                let white = hable(11.2);
+                c.map(|x| hable(2.0 * x) / white)
+            }
+            ToneMapping::Aces => c.map(|x| {
+                let x = 0.6 * x;
+                x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14)
+            }),
+        };
+
+        match self.color_space {
+            ColorSpace::Srgb => c.map(|x| srgb_encode(x.clamp(0.0, 1.0))),
+            ColorSpace::DisplayP3 => {
+                transform(&REC709_TO_P3, c).map(|x| srgb_encode(x.clamp(0.0, 1.0)))
+            }
+            ColorSpace::Rec2020 => {
+                transform(&REC709_TO_REC2020, c).map(|x| rec709_encode(x.clamp(0.0, 1.0)))
+            }
+        }
+    }
+
+    pub fn apply_image(&self, image: &Image) -> Image {
+        image.map(|c| self.apply(c))
+    }
+}
+
+// Von Kries adaptation in Bradford cone space, from the white of a blackbody
+// at `temperature` to the neutral one.
+fn adapt(color: Color, temperature: f64) -> Color {
+    let lms = |t: f64| transform(&XYZ_TO_LMS, planckian_xyz(t));
+    let (source, target) = (lms(temperature), lms(NEUTRAL_TEMPERATURE));
+    let scale = Vec3::new(
+        target.x() / source.x(),
+        target.y() / source.y(),
+        target.z() / source.z(),
+    );
+
+    let cone = transform(&XYZ_TO_LMS, transform(&RGB_TO_XYZ, color));
+    xyz_to_linear_srgb(transform(&LMS_TO_XYZ, scale * cone))
+}
+
+// XYZ of the blackbody white with luminance 1, from the cubic fit of the
+// Planckian locus by Kim et al. (2002). Valid from 1667K to 25000K.
+fn planckian_xyz(temperature: f64) -> Vec3 {
+    let t = temperature.clamp(1667.0, 25000.0);
+    let (t2, t3) = (t * t, t * t * t);
+    let x = if t <= 4000.0 {
+        -0.2661239e9 / t3 - 0.2343589e6 / t2 + 0.8776956e3 / t + 0.179910
+    } else {
+        -3.0258469e9 / t3 + 2.1070379e6 / t2 + 0.2226347e3 / t + 0.240390
+    };
+    let (x2, x3) = (x * x, x * x * x);
+    let y = if t <= 2222.0 {
+        -1.1063814 * x3 - 1.34811020 * x2 + 2.18555832 * x - 0.20219683
+    } else if t <= 4000.0 {
+        -0.9549476 * x3 - 1.37418593 * x2 + 2.09137015 * x - 0.16748867
+    } else {
+        3.0817580 * x3 - 5.87338670 * x2 + 3.75112997 * x - 0.37001483
+    };
+    Vec3::new(x / y, 1.0, (1.0 - x - y) / y)
+}
+
+fn hable(x: f64) -> f64 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
+
+fn srgb_encode(x: f64) -> f64 {
+    if x <= 0.0031308 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Inverse of the sRGB transfer curve: display values in [0, 1] back to
+/// linear ones.
+pub fn srgb_decode(x: f64) -> f64 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn rec709_encode(x: f64) -> f64 {
+    if x < 0.018 {
+        4.5 * x
+    } else {
+        1.099 * x.powf(0.45) - 0.099
+    }
+}
+
+fn transform(m: &[[f64; 3]; 3], v: Vec3) -> Vec3 {
+    Vec3::new(
+        m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+        m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+        m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_only_encode() {
+        let tone_map = ToneMap::default();
+        let c = tone_map.apply(Color::new(0.0, 0.18, 2.0));
+        assert!(c.x().abs() < 1e-9);
+        assert!((c.y() - 0.46135).abs() < 1e-4);
+        assert!((c.z() - 1.0).abs() < 1e-9);
+
+        // white balancing at the neutral temperature changes nothing.
+        let balanced = ToneMap {
+            white_balance: Some(NEUTRAL_TEMPERATURE),
+            ..tone_map
+        };
+        let grey = Color::new(0.3, 0.3, 0.3);
+        assert!((balanced.apply(grey) - tone_map.apply(grey)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn test_srgb_round_trip() {
+        assert!((srgb_decode(0.5) - 0.21404).abs() < 1e-5);
+        for x in [0.0, 0.002, 0.0031308, 0.01, 0.18, 0.5, 1.0] {
+            assert!((srgb_decode(srgb_encode(x)) - x).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_curves_stay_in_range() {
+        for tone_mapping in [
+            ToneMapping::Reinhard { white: 4.0 },
+            ToneMapping::Hable,
+            ToneMapping::Aces,
+        ] {
+            for color_space in [ColorSpace::Srgb, ColorSpace::DisplayP3, ColorSpace::Rec2020] {
+                let tone_map = ToneMap {
+                    tone_mapping,
+                    color_space,
+                    ..Default::default()
+                };
+                let mut last = -1.0;
+                for x in [0.0, 0.01, 0.1, 1.0, 10.0, 100.0] {
+                    let c = tone_map.apply(Color::new(x, x, x));
+                    assert!((0.0..=1.0).contains(&c.y()), "{:?}", tone_map);
+                    // greys stay grey.
+                    assert!((c.x() - c.y()).abs() < 1e-4 && (c.z() - c.y()).abs() < 1e-4);
+                    assert!(c.y() >= last);
+                    last = c.y();
+                }
+            }
+        }
+
+        // a warm light balanced as neutral turns white light blue.
+        let warm = ToneMap {
+            white_balance: Some(3200.0),
+            ..Default::default()
+        };
+        let c = warm.apply(Color::new(0.5, 0.5, 0.5));
+        assert!(c.z() > c.x());
+    }
+}
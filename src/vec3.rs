@@ -46,6 +46,11 @@ impl Vec3 {
             && self.e[1] < NEAR_ZERO_THRESHOLD
             && self.e[2] < NEAR_ZERO_THRESHOLD
     }
+
+    /// Applies `f` to each component.
+    pub fn map<F: Fn(f64) -> f64>(&self, f: F) -> Self {
+        Self::new(f(self.x()), f(self.y()), f(self.z()))
+    }
 }
 
 pub fn dot(a: Vec3, b: Vec3) -> f64 {
@@ -60,52 +65,54 @@ pub fn cross(a: Vec3, b: Vec3) -> Vec3 {
     )
 }
 
-pub fn random() -> Vec3 {
+pub fn random<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
     Vec3::new(
-        rand::rng().random::<f64>(),
-        rand::rng().random::<f64>(),
-        rand::rng().random::<f64>(),
+        rng.random::<f64>(),
+        rng.random::<f64>(),
+        rng.random::<f64>(),
     )
 }
 
-pub fn random_range(min: f64, max: f64) -> Vec3 {
+pub fn random_range<R: Rng + ?Sized>(rng: &mut R, min: f64, max: f64) -> Vec3 {
     Vec3::new(
-        rand::rng().random_range(min..max),
-        rand::rng().random_range(min..max),
-        rand::rng().random_range(min..max),
+        rng.random_range(min..max),
+        rng.random_range(min..max),
+        rng.random_range(min..max),
     )
 }
 
-pub fn random_unit_vector() -> Vec3 {
-    loop {
-        let p = random_range(-1.0, 1.0);
-        let normsq = p.norm_sq();
-        if 1e-100 < normsq && normsq <= 1.0 {
-            return p / normsq.sqrt();
-        }
-    }
+/// Maps a uniform 2D sample to a uniformly distributed unit vector.
+pub fn sample_unit_vector(u: [f64; 2]) -> Vec3 {
+    let z = 1.0 - 2.0 * u[0];
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * std::f64::consts::PI * u[1];
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
 }
 
-pub fn random_on_hemisphere(normal: Vec3) -> Vec3 {
-    let random_unit_vec = random_unit_vector();
-    if dot(normal, random_unit_vec) > 0.0 {
-        random_unit_vec
+pub fn sample_hemisphere(normal: Vec3, u: [f64; 2]) -> Vec3 {
+    let unit_vec = sample_unit_vector(u);
+    if dot(normal, unit_vec) > 0.0 {
+        unit_vec
     } else {
-        -random_unit_vec
+        -unit_vec
     }
 }
 
-pub fn random_on_unit_disk() -> Vec3 {
-    loop {
-        let p = Vec3::new(
-            rand::rng().random_range(-1.0..1.0),
-            rand::rng().random_range(-1.0..1.0),
-            0.0,
-        );
-        if p.norm_sq() < 1.0 {
-            return p;
-        }
+/// Maps a uniform 2D sample onto the unit disk, keeping strata intact
+/// (Shirley and Chiu's concentric mapping).
+pub fn sample_unit_disk(u: [f64; 2]) -> Vec3 {
+    let (a, b) = (2.0 * u[0] - 1.0, 2.0 * u[1] - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3::new(0.0, 0.0, 0.0);
     }
+
+    let quarter_pi = std::f64::consts::FRAC_PI_4;
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, quarter_pi * (b / a))
+    } else {
+        (b, 2.0 * quarter_pi - quarter_pi * (a / b))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
 }
 
 pub fn reflect(vector: Vec3, normal: Vec3) -> Vec3 {
@@ -234,6 +241,7 @@ impl DivAssign<f64> for Vec3 {
 // normalize to 1
 
 #[cfg(test)]
+#[allow(clippy::clone_on_copy)]
 mod test {
     use super::*;
 